@@ -0,0 +1,105 @@
+//! Embeds what `version --verbose` reports: the git commit, build date, rustc
+//! version and the linera crates compiled in.
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// linera crates whose versions pin the protocol this binary speaks
+const LINERA_CRATES: &[&str] = &[
+    "linera-base",
+    "linera-chain",
+    "linera-client",
+    "linera-core",
+    "linera-execution",
+    "linera-storage",
+    "linera-views",
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let sha = output("git", &["rev-parse", "--short=12", "HEAD"]);
+    let dirty = output("git", &["status", "--porcelain", "--untracked-files=no"])
+        .is_some_and(|status| !status.is_empty());
+    let sha = match sha {
+        Some(sha) if dirty => format!("{}-dirty", sha),
+        Some(sha) => sha,
+        None => "unknown".to_string(),
+    };
+    println!("cargo:rustc-env=PWS_GIT_SHA={}", sha);
+
+    println!("cargo:rustc-env=PWS_BUILD_DATE={}", build_date());
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=PWS_RUSTC_VERSION={}", rustc_version);
+
+    println!("cargo:rustc-env=PWS_LINERA_VERSIONS={}", linera_versions());
+}
+
+/// Trimmed stdout of a successful command.
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Today's UTC date as `YYYY-MM-DD`, or that of `SOURCE_DATE_EPOCH` for
+/// reproducible builds.
+fn build_date() -> String {
+    let secs = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_secs())
+        });
+
+    // Days since the epoch to a civil date, after Howard Hinnant's `civil_from_days`.
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// `name version` of each of [`LINERA_CRATES`] found in `Cargo.lock`.
+fn linera_versions() -> String {
+    let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let mut versions = Vec::new();
+    let mut lines = lock.lines();
+    while let Some(line) = lines.next() {
+        let Some(name) = line
+            .strip_prefix("name = \"")
+            .and_then(|rest| rest.strip_suffix('"'))
+        else {
+            continue;
+        };
+        if !LINERA_CRATES.contains(&name) {
+            continue;
+        }
+        if let Some(version) = lines
+            .next()
+            .and_then(|line| line.strip_prefix("version = \""))
+            .and_then(|rest| rest.strip_suffix('"'))
+        {
+            versions.push(format!("{} {}", name, version));
+        }
+    }
+    if versions.is_empty() {
+        return "unknown".to_string();
+    }
+    versions.join(", ")
+}
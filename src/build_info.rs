@@ -0,0 +1,24 @@
+//! What this binary was built from, embedded by `build.rs`.
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_SHA: &str = env!("PWS_GIT_SHA");
+pub const BUILD_DATE: &str = env!("PWS_BUILD_DATE");
+pub const RUSTC_VERSION: &str = env!("PWS_RUSTC_VERSION");
+/// Comma-separated `name version` of the linera crates compiled in
+pub const LINERA_VERSIONS: &str = env!("PWS_LINERA_VERSIONS");
+
+/// Prints the version, and with `verbose` everything needed to pin down a
+/// build in a bug report.
+pub fn print(verbose: bool) {
+    println!("{} {}", env!("CARGO_PKG_NAME"), VERSION);
+    if !verbose {
+        return;
+    }
+    println!("commit:  {}", GIT_SHA);
+    println!("built:   {}", BUILD_DATE);
+    println!("rustc:   {}", RUSTC_VERSION);
+    println!("linera:");
+    for krate in LINERA_VERSIONS.split(", ") {
+        println!("  {}", krate);
+    }
+}
@@ -1,15 +1,28 @@
 // Copyright (c) Zefchain Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use linera_base::identifiers::{AccountOwner, ApplicationId};
+use anyhow::Context;
+use async_trait::async_trait;
+use linera_base::{
+    crypto::CryptoHash,
+    data_types::BlockHeight,
+    identifiers::{AccountOwner, ApplicationId, ChainId},
+};
 use linera_core::client::ChainClient;
+use std::sync::Arc;
 
+use super::batch::Batch;
+use super::retry::{submit_with_retry, RetryPolicy};
+use super::source::QuerySource;
 use crate::client::{Client, Environment};
+use crate::graphql;
+use crate::limits::check_response_size;
 
 pub struct Application {
     pub(crate) client: Client,
     pub(crate) chain_client: ChainClient<Environment>,
     pub(crate) id: ApplicationId,
+    pub(crate) source: Arc<dyn QuerySource>,
 }
 
 pub struct QueryOptions {
@@ -17,21 +30,92 @@ pub struct QueryOptions {
     pub owner: Option<AccountOwner>,
 }
 
+/// What the sync targets need of an [`Application`]: answers to GraphQL
+/// queries. Targets depend on this rather than on [`Application`], whose
+/// chain client needs a live network, so their diff and write logic can run
+/// against an executor returning canned responses.
+#[async_trait]
+pub trait QueryExecutor: Send + Sync {
+    async fn query(&self, query: &str) -> Result<String, anyhow::Error>;
+}
+
+#[async_trait]
+impl QueryExecutor for Application {
+    async fn query(&self, query: &str) -> Result<String, anyhow::Error> {
+        Application::query(self, query).await
+    }
+}
+
 impl Application {
+    /// The chain the application is queried on.
+    pub fn chain_id(&self) -> ChainId {
+        self.chain_client.chain_id()
+    }
+
+    /// The height of the next block on the application's chain.
+    ///
+    /// # Errors
+    /// If the chain state can't be read from local storage.
+    pub async fn next_block_height(&self) -> Result<BlockHeight, anyhow::Error> {
+        Ok(self.chain_client.chain_info().await?.next_block_height)
+    }
+
+    /// Checks that the application exists on this chain and its service
+    /// answers a trivial query, so a mistyped id fails fast instead of
+    /// subscribing to notifications that never come.
+    ///
+    /// # Errors
+    /// If the application doesn't respond, typically because the id is wrong.
+    pub async fn ping(&self) -> Result<(), anyhow::Error> {
+        self.query(&graphql::ping().to_json())
+            .await
+            .with_context(|| format!("application {} did not respond", self.id))?;
+        Ok(())
+    }
+
     /// Performs a query against an application's service.
     ///
     /// If `block_hash` is non-empty, it specifies the block at which to
     /// perform the query; otherwise, the latest block is used.
     ///
     /// # Errors
-    /// If the application ID is invalid, the query is incorrect, or
-    /// the response isn't valid UTF-8.
+    /// If the application ID is invalid, the query is incorrect, or the
+    /// response isn't valid UTF-8 or exceeds `--max-response-bytes`. A
+    /// validator that timed out surfaces as
+    /// [`ConnectTimeout`](crate::client::ClientError::ConnectTimeout) or
+    /// [`ReadTimeout`](crate::client::ClientError::ReadTimeout).
     ///
     /// # Panics
     /// On internal protocol errors.
+    pub async fn query(&self, query: &str) -> Result<String, anyhow::Error> {
+        self.source.query(self, query).await
+    }
+
+    /// Sends several independent queries as one request and returns their
+    /// responses in order, each shaped as if sent on its own. See
+    /// [`batch`](super::batch) for what can be merged.
+    ///
+    /// # Errors
+    /// If the queries can't be merged, the request fails, or the response
+    /// reports GraphQL errors. Callers can then fall back to [`Application::query`].
+    pub async fn query_batch(&self, queries: &[&str]) -> Result<Vec<String>, anyhow::Error> {
+        let batch = Batch::new(queries)?;
+        let response = self.query(&batch.request).await?;
+        batch.split(&response)
+    }
+
+    /// Answers future queries from `source` instead of the network, see
+    /// [`source`](super::source).
+    pub fn with_source(mut self, source: Arc<dyn QuerySource>) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Sends `query` to the application's service, whatever the configured
+    /// [`QuerySource`].
     // TODO(#5253) allow passing bytes here rather than just strings
     // TODO(#5152) a lot of this logic is shared with `linera_service::node_service`
-    pub async fn query(&self, query: &str) -> Result<String, anyhow::Error> {
+    pub(crate) async fn query_network(&self, query: &str) -> Result<String, anyhow::Error> {
         let chain_client = self.chain_client.clone();
         // if let Some(owner) = owner {
         //     chain_client.set_preferred_owner(owner);
@@ -52,7 +136,8 @@ impl Application {
                 },
                 None,
             )
-            .await?
+            .await
+            .map_err(|e| self.client.timeouts.explain(e))?
         else {
             panic!("system response to user query")
         };
@@ -66,9 +151,46 @@ impl Application {
                 .apply_client_command(&chain_client, |_chain_client| {
                     chain_client.execute_operations(operations.clone(), vec![])
                 })
-                .await?;
+                .await
+                .map_err(|e| self.client.timeouts.explain(e))?;
         }
 
+        check_response_size(response.len())?;
         Ok(String::from_utf8(response)?)
     }
+
+    /// Submits a raw operation, serialized with the application's ABI, to the
+    /// application and returns the hash of the block that included it.
+    ///
+    /// Operations produced by GraphQL mutations are already submitted by
+    /// [`Application::query`]; this is for callers holding the encoded bytes.
+    /// Transient RPC errors are retried without ever submitting the operation
+    /// twice, see [`retry`](super::retry).
+    ///
+    /// # Errors
+    /// If the block proposal fails, the operation is rejected, or its outcome
+    /// is unknown after a transient error.
+    pub async fn execute_operation(&self, bytes: Vec<u8>) -> Result<CryptoHash, anyhow::Error> {
+        let operation = linera_execution::Operation::User {
+            application_id: self.id,
+            bytes,
+        };
+        let operation = &operation;
+
+        let certificate =
+            submit_with_retry(&self.chain_client, RetryPolicy::default(), || async move {
+                Ok(self
+                    .client
+                    .client_context
+                    .lock()
+                    .await
+                    .apply_client_command(&self.chain_client, |_chain_client| {
+                        self.chain_client.execute_operation(operation.clone())
+                    })
+                    .await?)
+            })
+            .await?;
+
+        Ok(certificate.hash())
+    }
 }
@@ -0,0 +1,234 @@
+//! Merges several GraphQL queries into one request and splits the response
+//! back, so independent top-level queries cost a single round-trip.
+//!
+//! Only plain `query { ... }` requests without variables are merged; their
+//! top-level fields must not collide.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    query: String,
+}
+
+/// The selection set of a `{ "query": "query { ... }" }` request body,
+/// without its outer braces.
+fn selection(request: &str) -> Result<String> {
+    let request: Request = serde_json::from_str(request)
+        .with_context(|| format!("can't batch request {}", request))?;
+    let body = request.query.trim();
+    let body = body.strip_prefix("query").unwrap_or(body).trim();
+    body.strip_prefix('{')
+        .and_then(|body| body.strip_suffix('}'))
+        .map(|inner| inner.trim().to_string())
+        .with_context(|| format!("can't batch `{}`, only anonymous queries are", body))
+}
+
+/// Names of the top-level fields of a selection set, as they appear in the
+/// response: the alias when there is one.
+fn top_level_fields(selection: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let (mut braces, mut parens) = (0usize, 0usize);
+    let (mut in_string, mut escaped) = (false, false);
+    let mut token = String::new();
+    let mut after_alias = false;
+
+    let flush = |token: &mut String, fields: &mut Vec<String>, after_alias: &mut bool| {
+        if !token.is_empty() {
+            if *after_alias {
+                *after_alias = false;
+            } else {
+                fields.push(token.clone());
+            }
+            token.clear();
+        }
+    };
+
+    for c in selection.chars() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else {
+                in_string = c != '"';
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => {
+                flush(&mut token, &mut fields, &mut after_alias);
+                braces += 1;
+            }
+            '}' => braces = braces.saturating_sub(1),
+            '(' => {
+                flush(&mut token, &mut fields, &mut after_alias);
+                parens += 1;
+            }
+            ')' => parens = parens.saturating_sub(1),
+            ':' if braces == 0 && parens == 0 => {
+                // `alias: field`, keep the alias and skip the field after it.
+                flush(&mut token, &mut fields, &mut after_alias);
+                after_alias = true;
+            }
+            c if braces == 0 && parens == 0 && (c.is_alphanumeric() || c == '_') => token.push(c),
+            _ if braces == 0 && parens == 0 => flush(&mut token, &mut fields, &mut after_alias),
+            _ => {}
+        }
+    }
+    flush(&mut token, &mut fields, &mut after_alias);
+    fields
+}
+
+/// A batch of queries merged into one request.
+pub struct Batch {
+    /// The merged request body
+    pub request: String,
+    /// Top-level fields answering each of the original queries
+    fields: Vec<Vec<String>>,
+}
+
+impl Batch {
+    /// Merges `requests` into a single query.
+    ///
+    /// # Errors
+    /// If a request isn't a plain anonymous query, or two of them select the
+    /// same top-level field.
+    pub fn new(requests: &[&str]) -> Result<Self> {
+        let selections = requests
+            .iter()
+            .map(|request| selection(request))
+            .collect::<Result<Vec<_>>>()?;
+        let fields: Vec<Vec<String>> = selections.iter().map(|s| top_level_fields(s)).collect();
+
+        let mut seen = std::collections::HashSet::new();
+        for field in fields.iter().flatten() {
+            if !seen.insert(field) {
+                anyhow::bail!("can't batch queries both selecting `{}`", field);
+            }
+        }
+
+        let query = format!("query {{ {} }}", selections.join(" "));
+        Ok(Self {
+            request: serde_json::json!({ "query": query }).to_string(),
+            fields,
+        })
+    }
+
+    /// Splits the response to the merged request into one response per
+    /// original query, each shaped as if it had been sent on its own.
+    ///
+    /// # Errors
+    /// If the response carries GraphQL errors, which can't be attributed to
+    /// a single query, or has no `data`.
+    pub fn split(&self, response: &str) -> Result<Vec<String>> {
+        let mut response: Map<String, Value> =
+            serde_json::from_str(response).context("invalid batched response")?;
+        if response.get("errors").is_some_and(|e| !e.is_null()) {
+            anyhow::bail!(
+                "batched query failed: {}",
+                response.get("errors").unwrap_or(&Value::Null)
+            );
+        }
+        let Some(Value::Object(mut data)) = response.remove("data") else {
+            anyhow::bail!("batched response has no data");
+        };
+
+        Ok(self
+            .fields
+            .iter()
+            .map(|fields| {
+                let answered: Map<String, Value> = fields
+                    .iter()
+                    .filter_map(|field| data.remove_entry(field))
+                    .collect();
+                serde_json::json!({ "data": answered }).to_string()
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(query: &str) -> String {
+        serde_json::json!({ "query": query }).to_string()
+    }
+
+    #[test]
+    fn selection_strips_the_query_keyword_and_braces() {
+        assert_eq!(
+            selection(&request("query { a { id } }")).unwrap(),
+            "a { id }"
+        );
+        assert_eq!(selection(&request("{ b }")).unwrap(), "b");
+        assert!(selection(&request("query Named($x: Int) { a }")).is_err());
+        assert!(selection(r#"{"query": "{ a }", "variables": {}}"#).is_err());
+    }
+
+    #[test]
+    fn top_level_fields_use_aliases() {
+        assert_eq!(top_level_fields("a: foo b : bar baz"), ["a", "b", "baz"]);
+    }
+
+    #[test]
+    fn top_level_fields_skip_arguments_and_nested_selections() {
+        assert_eq!(
+            top_level_fields("tournaments(first: 10, after: \"x\") { id name } count"),
+            ["tournaments", "count"]
+        );
+        assert_eq!(
+            top_level_fields("a: player(id: 1) { stats { wins } }"),
+            ["a"]
+        );
+    }
+
+    #[test]
+    fn top_level_fields_ignore_string_literals() {
+        assert_eq!(
+            top_level_fields(r#"player(name: "x: { y") { id } other"#),
+            ["player", "other"]
+        );
+        assert_eq!(
+            top_level_fields(r#"player(name: "say \"hi\" { z") { id } other"#),
+            ["player", "other"]
+        );
+    }
+
+    #[test]
+    fn colliding_fields_are_rejected() {
+        let a = request("query { tournaments { id } }");
+        let b = request("query { tournaments { name } }");
+        assert!(Batch::new(&[&a, &b]).is_err());
+
+        let aliased = request("query { other: tournaments { name } }");
+        assert!(Batch::new(&[&a, &aliased]).is_ok());
+    }
+
+    #[test]
+    fn split_answers_each_query() {
+        let a = request("query { a { id } }");
+        let b = request("query { x: b }");
+        let batch = Batch::new(&[&a, &b]).unwrap();
+        let parts = batch
+            .split(r#"{"data": {"a": {"id": 1}, "x": 2}}"#)
+            .unwrap();
+        assert_eq!(parts, [r#"{"data":{"a":{"id":1}}}"#, r#"{"data":{"x":2}}"#]);
+    }
+
+    #[test]
+    fn split_fails_on_errors_or_missing_data() {
+        let a = request("query { a }");
+        let batch = Batch::new(&[&a]).unwrap();
+        assert!(batch
+            .split(r#"{"data": null, "errors": [{"message": "boom"}]}"#)
+            .is_err());
+        assert!(batch.split(r#"{"errors": null}"#).is_err());
+        assert!(batch.split(r#"{"data": {"a": 1}, "errors": null}"#).is_ok());
+    }
+}
@@ -2,12 +2,28 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use futures::StreamExt;
-use linera_base::identifiers::AccountOwner;
+use linera_base::{
+    crypto::CryptoHash,
+    data_types::{Amount, BlockHeight, Epoch},
+    identifiers::{AccountOwner, ApplicationId, ChainId},
+};
 use linera_core::client::ChainClient;
+use linera_core::worker::Notification;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
 pub mod application;
-use crate::client::{Client, Environment};
-pub use application::Application;
+pub mod batch;
+mod queue;
+pub mod retry;
+pub mod source;
+use crate::client::{synchronize_with_retry, Client, ClientEvent, Environment};
+use crate::util::Backoff;
+pub use application::{Application, QueryExecutor};
+use queue::NotificationQueue;
+use retry::{submit_with_retry, RetryPolicy};
 
 #[derive(Clone)]
 pub struct Chain {
@@ -15,17 +31,62 @@ pub struct Chain {
     pub(crate) chain_client: ChainClient<Environment>,
 }
 
+/// Where a transfer landed on the sender's chain
+#[derive(Clone, Copy, Debug)]
+pub struct TransferReceipt {
+    pub hash: CryptoHash,
+    pub height: BlockHeight,
+}
+
 pub struct TransferParams {
     pub donor: Option<AccountOwner>,
     pub amount: u64,
     pub recipient: linera_base::identifiers::Account,
 }
 
+/// How [`Chain::on_notification_with`] keeps its subscription alive
+#[derive(Clone, Debug)]
+pub struct SubscriptionOptions {
+    /// Resubscribe when no notification arrives within this long; `None` waits forever
+    pub idle_timeout: Option<Duration>,
+    /// Pause before resubscribing after the stream ended or stalled, backed off
+    /// up to `max_resubscribe_delay` while resubscribing keeps failing
+    pub resubscribe_delay: Duration,
+    pub max_resubscribe_delay: Duration,
+    /// Consecutive failed resubscriptions before giving up; `None` retries forever
+    pub max_reconnect_attempts: Option<u32>,
+    /// Notifications queued while the callback is busy, the oldest dropped beyond that
+    pub queue_depth: usize,
+    /// Stops the subscription once cancelled, after the running callback finished
+    pub cancel: CancellationToken,
+}
+
+impl Default for SubscriptionOptions {
+    fn default() -> Self {
+        Self {
+            idle_timeout: None,
+            resubscribe_delay: Duration::from_secs(1),
+            max_resubscribe_delay: Duration::from_secs(60),
+            max_reconnect_attempts: None,
+            queue_depth: 64,
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+/// How [`Chain::add_owner`] adds an owner
 pub struct AddOwnerOptions {
+    /// Weight of the owner in leader election; existing super owners become
+    /// regular owners of weight 100
     pub weight: u64,
 }
 
 impl Chain {
+    /// The id of the chain this client is bound to.
+    pub fn chain_id(&self) -> ChainId {
+        self.chain_client.chain_id()
+    }
+
     /// Sets a callback to be called when a notification is received
     /// from the network.
     ///
@@ -34,20 +95,199 @@ impl Chain {
     ///
     /// # Panics
     /// If the handler function fails.
-    pub fn on_notification<F, Fut>(&self, f: F)
+    pub fn on_notification<F, Fut>(&self, f: F) -> JoinHandle<()>
     where
         F: Fn() -> Fut + Send + 'static,
         Fut: std::future::Future<Output = ()> + Send + 'static,
     {
-        let mut notifications = self.chain_client.subscribe().unwrap();
+        self.on_notification_with(SubscriptionOptions::default(), f)
+    }
+
+    /// Same as [`Chain::on_notification`], but resubscribes when the stream
+    /// ends or, with an `idle_timeout`, when it stops delivering notifications.
+    /// The callback runs once after every resubscription to catch up on
+    /// anything missed in between.
+    ///
+    /// When a notification shows the chain moved to a new epoch, the chain is
+    /// re-synchronized from the new committee before the callback runs, and a
+    /// [`ClientEvent::CommitteeChanged`] is published.
+    ///
+    /// The returned task ends once `options.cancel` is cancelled, or after
+    /// `options.max_reconnect_attempts` resubscriptions failed in a row.
+    ///
+    /// # Panics
+    /// If the initial subscription fails.
+    pub fn on_notification_with<F, Fut>(&self, options: SubscriptionOptions, f: F) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.on_notification_with_details(options, move |_notification| f())
+    }
+
+    /// Same as [`Chain::on_notification_with`], but passes the notification to
+    /// the callback so it can branch on its reason and query selectively.
+    ///
+    /// The callback gets `None` for the catch-up run after a resubscription,
+    /// when notifications may have been missed and everything should be
+    /// re-queried.
+    ///
+    /// Notifications are received into a queue of `options.queue_depth`
+    /// entries while the callback works through them one at a time; see
+    /// [`NotificationQueue::push`] for what happens when it fills up.
+    ///
+    /// # Panics
+    /// If the initial subscription fails.
+    pub fn on_notification_with_details<F, Fut>(
+        &self,
+        options: SubscriptionOptions,
+        f: F,
+    ) -> JoinHandle<()>
+    where
+        F: Fn(Option<Notification>) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let client = self.client.clone();
+        let chain_client = self.chain_client.clone();
+        let mut notifications = chain_client.subscribe().unwrap();
         tokio::spawn(async move {
-            while let Some(_notification) = notifications.next().await {
-                // if let Reason::BlockExecuted { .. } = notification.reason {
-                // This will run only for NewBlock, regardless of its fields
-                f().await
-                // }
-            }
-        });
+            let chain_id = chain_client.chain_id();
+            let queue = NotificationQueue::new(options.queue_depth);
+            // Also stops processing when receiving gave up.
+            let stop = options.cancel.child_token();
+
+            let receive = async {
+                let mut epoch = None;
+                let mut failed_attempts = 0;
+                let backoff =
+                    Backoff::new(options.resubscribe_delay, options.max_resubscribe_delay);
+                let stopped =
+                    || tracing::info!("Stopped listening to notifications on chain {}", chain_id);
+                resync_on_epoch_change(&client, &chain_client, &mut epoch).await;
+                loop {
+                    let wait = async {
+                        match options.idle_timeout {
+                            Some(idle) => {
+                                match tokio::time::timeout(idle, notifications.next()).await {
+                                    Ok(next) => next,
+                                    Err(_) => {
+                                        tracing::warn!(
+                                            "No notification on chain {} for {:?}, resubscribing",
+                                            chain_id,
+                                            idle
+                                        );
+                                        None
+                                    }
+                                }
+                            }
+                            None => notifications.next().await,
+                        }
+                    };
+                    let next = tokio::select! {
+                        next = wait => next,
+                        () = options.cancel.cancelled() => return stopped(),
+                    };
+
+                    match next {
+                        Some(notification) => {
+                            resync_on_epoch_change(&client, &chain_client, &mut epoch).await;
+                            queue.push(Some(notification))
+                        }
+                        None => {
+                            // Neither the backoff nor the catch-up sync may hold up a shutdown.
+                            tokio::select! {
+                                () = tokio::time::sleep(backoff.delay(failed_attempts)) => {}
+                                () = options.cancel.cancelled() => return stopped(),
+                            }
+                            match chain_client.subscribe() {
+                                Ok(stream) => {
+                                    failed_attempts = 0;
+                                    notifications = stream;
+                                    tracing::info!(
+                                        "Resubscribed to notifications on chain {}",
+                                        chain_id
+                                    );
+                                    let synced = tokio::select! {
+                                        synced = chain_client.synchronize_from_validators() => synced,
+                                        () = options.cancel.cancelled() => return stopped(),
+                                    };
+                                    if let Err(e) = synced {
+                                        tracing::error!(
+                                            "Failed to synchronize chain {}: {}",
+                                            chain_id,
+                                            client.timeouts.explain(e)
+                                        );
+                                    }
+                                    resync_on_epoch_change(&client, &chain_client, &mut epoch)
+                                        .await;
+                                    queue.push(None)
+                                }
+                                Err(e) => {
+                                    tracing::error!(
+                                        "Failed to resubscribe on chain {}: {}",
+                                        chain_id,
+                                        e
+                                    );
+                                    failed_attempts += 1;
+                                    if options
+                                        .max_reconnect_attempts
+                                        .is_some_and(|max| failed_attempts >= max)
+                                    {
+                                        tracing::error!(
+                                            "Giving up on notifications for chain {} after {} failed resubscriptions",
+                                            chain_id,
+                                            failed_attempts
+                                        );
+                                        stop.cancel();
+                                        return;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            };
+
+            let process = async {
+                loop {
+                    // A callback that already started runs to completion before this is checked.
+                    let next = tokio::select! {
+                        next = queue.pop() => next,
+                        () = stop.cancelled() => return,
+                    };
+                    f(next).await
+                }
+            };
+
+            tokio::join!(receive, process);
+        })
+    }
+
+    /// Synchronizes the chain from validators and processes its inbox right
+    /// away, without waiting for a notification, e.g. after a message is known
+    /// to have been missed. Applied messages are published as
+    /// [`ClientEvent::InboxProcessed`].
+    ///
+    /// # Errors
+    /// If the chain can't be synchronized, its inbox processed or the wallet
+    /// updated.
+    pub async fn process_inbox(&self) -> Result<(), anyhow::Error> {
+        let chain_id = self.chain_id();
+        synchronize_with_retry(&self.chain_client, self.client.timeouts).await?;
+        let (certificates, _) = self.chain_client.process_inbox().await?;
+        self.client.report_inbox(chain_id, &certificates);
+        self.client
+            .client_context
+            .lock()
+            .await
+            .update_wallet(&self.chain_client)
+            .await?;
+        tracing::info!(
+            "Processed the inbox of chain {} in {} block(s)",
+            chain_id,
+            certificates.len()
+        );
+        Ok(())
     }
 
     /// Gets the balance of the default chain.
@@ -58,15 +298,223 @@ impl Chain {
         Ok(self.chain_client.query_balance().await?.to_string())
     }
 
-    /// Retrieves an application for querying.
+    /// Transfers `amount` tokens from the donor, or the chain balance, to
+    /// `recipient`, retrying on transient RPC errors without ever submitting
+    /// the transfer twice (see [`retry`]).
+    ///
+    /// The transfer is only sent once this returns: the recipient sees the
+    /// funds after processing its inbox, see [`Chain::wait_for_receipt`].
+    ///
+    /// # Errors
+    /// If the transfer is rejected, e.g. for insufficient funds, or its outcome
+    /// is unknown after a transient error.
+    pub async fn transfer(&self, params: TransferParams) -> Result<TransferReceipt, anyhow::Error> {
+        let owner = params.donor.unwrap_or(AccountOwner::CHAIN);
+        let amount = Amount::from_tokens(params.amount.into());
+
+        let certificate =
+            submit_with_retry(&self.chain_client, RetryPolicy::default(), || async move {
+                Ok(self
+                    .client
+                    .client_context
+                    .lock()
+                    .await
+                    .apply_client_command(&self.chain_client, |_chain_client| {
+                        self.chain_client.transfer(owner, amount, params.recipient)
+                    })
+                    .await?)
+            })
+            .await?;
+
+        Ok(TransferReceipt {
+            hash: certificate.hash(),
+            height: certificate.block().header.height,
+        })
+    }
+
+    /// Adds `owner` to the chain's owners, next to the current ones, so blocks
+    /// signed by either key are accepted.
     ///
     /// # Errors
-    /// If the application ID is invalid.
-    pub async fn application(&self, id: &str) -> Result<Application, anyhow::Error> {
-        Ok(Application {
+    /// If the ownership change is rejected, or its outcome is unknown after a
+    /// transient error.
+    pub async fn add_owner(
+        &self,
+        owner: AccountOwner,
+        options: AddOwnerOptions,
+    ) -> Result<CryptoHash, anyhow::Error> {
+        let weight = options.weight;
+        let certificate =
+            submit_with_retry(&self.chain_client, RetryPolicy::default(), || async move {
+                Ok(self
+                    .client
+                    .client_context
+                    .lock()
+                    .await
+                    .apply_client_command(&self.chain_client, |_chain_client| {
+                        self.chain_client.share_ownership(owner, weight)
+                    })
+                    .await?)
+            })
+            .await?;
+        tracing::info!("Added owner {} to chain {}", owner, self.chain_id());
+        Ok(certificate.hash())
+    }
+
+    /// Removes `owner` from the chain's owners, keeping the other owners and
+    /// the round settings as they are.
+    ///
+    /// # Errors
+    /// If `owner` doesn't own the chain or is its only owner, or the ownership
+    /// change is rejected.
+    pub async fn remove_owner(&self, owner: AccountOwner) -> Result<CryptoHash, anyhow::Error> {
+        let mut ownership = self.chain_client.query_chain_ownership().await?;
+        let removed =
+            ownership.super_owners.remove(&owner) | ownership.owners.remove(&owner).is_some();
+        if !removed {
+            anyhow::bail!("{} is not an owner of chain {}", owner, self.chain_id());
+        }
+        if ownership.super_owners.is_empty() && ownership.owners.is_empty() {
+            anyhow::bail!(
+                "{} is the only owner of chain {}, add another one first",
+                owner,
+                self.chain_id()
+            );
+        }
+
+        let certificate = submit_with_retry(&self.chain_client, RetryPolicy::default(), || {
+            let ownership = ownership.clone();
+            async move {
+                Ok(self
+                    .client
+                    .client_context
+                    .lock()
+                    .await
+                    .apply_client_command(&self.chain_client, |_chain_client| {
+                        self.chain_client.change_ownership(ownership.clone())
+                    })
+                    .await?)
+            }
+        })
+        .await?;
+        tracing::info!("Removed owner {} from chain {}", owner, self.chain_id());
+        Ok(certificate.hash())
+    }
+
+    /// Waits until `recipient` has processed the messages sent by the block of
+    /// this chain at `height`, e.g. a transfer from [`Chain::transfer`].
+    ///
+    /// The recipient's inbox is processed every `poll_interval`, which needs
+    /// the recipient chain to be in the wallet. If another client processes
+    /// that inbox first, the receipt isn't observed and the wait times out.
+    ///
+    /// # Errors
+    /// If the recipient chain isn't tracked by the wallet, its inbox can't be
+    /// processed, or nothing arrived within `timeout`.
+    pub async fn wait_for_receipt(
+        &self,
+        recipient: ChainId,
+        height: BlockHeight,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<(), anyhow::Error> {
+        let sender = self.chain_id();
+        let tracked = self
+            .client
+            .client_context
+            .lock()
+            .await
+            .wallet()
+            .chain_ids()
+            .contains(&recipient);
+        if !tracked {
+            anyhow::bail!(
+                "recipient chain {} is not in the wallet, can't confirm the receipt",
+                recipient
+            );
+        }
+
+        let mut events = self.client.subscribe_events();
+        let received = async {
+            loop {
+                self.client.chain(Some(recipient)).await?;
+                while let Ok(event) = events.try_recv() {
+                    if let ClientEvent::InboxProcessed { chain_id, messages } = event {
+                        if chain_id == recipient
+                            && messages
+                                .iter()
+                                .any(|m| m.origin == sender && m.height == height)
+                        {
+                            return Ok::<(), anyhow::Error>(());
+                        }
+                    }
+                }
+                tokio::time::sleep(poll_interval).await;
+            }
+        };
+
+        tokio::time::timeout(timeout, received).await.map_err(|_| {
+            anyhow::anyhow!(
+                "chain {} did not receive block {} of {} within {:?}",
+                recipient,
+                height,
+                sender,
+                timeout
+            )
+        })?
+    }
+
+    /// Retrieves an application for querying over the network.
+    pub fn application(&self, id: ApplicationId) -> Application {
+        Application {
             client: self.client.clone(),
             chain_client: self.chain_client.clone(),
-            id: id.parse()?,
-        })
+            id,
+            source: Arc::new(source::Network),
+        }
+    }
+}
+
+/// Re-synchronizes `chain_client` from the current validator set if its epoch
+/// differs from `known`, which is updated to the current epoch. The first call
+/// only records the epoch.
+async fn resync_on_epoch_change(
+    client: &Client,
+    chain_client: &ChainClient<Environment>,
+    known: &mut Option<Epoch>,
+) {
+    let chain_id = chain_client.chain_id();
+    let current = match chain_client.chain_info().await {
+        Ok(info) => info.epoch,
+        Err(e) => {
+            tracing::error!("Failed to read the epoch of chain {}: {}", chain_id, e);
+            return;
+        }
+    };
+
+    let Some(previous) = known.replace(current) else {
+        return;
+    };
+    if previous == current {
+        return;
+    }
+
+    tracing::info!(
+        "Chain {} moved from epoch {} to {}, re-synchronizing from the new committee",
+        chain_id,
+        previous,
+        current
+    );
+    if let Err(e) = chain_client.synchronize_from_validators().await {
+        tracing::error!(
+            "Failed to synchronize chain {}: {}",
+            chain_id,
+            client.timeouts.explain(e)
+        );
     }
+    client.publish(ClientEvent::CommitteeChanged {
+        chain_id,
+        previous,
+        current,
+    });
 }
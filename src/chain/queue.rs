@@ -0,0 +1,71 @@
+//! A bounded queue between receiving notifications and processing them, so
+//! a slow callback shows up as queue depth instead of a silently backed-up
+//! stream.
+
+use linera_core::worker::Notification;
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+use crate::metrics::METRICS;
+
+/// Notifications waiting for the callback, `None` standing for a catch-up
+/// run that re-queries everything
+pub(crate) struct NotificationQueue {
+    capacity: usize,
+    items: Mutex<VecDeque<Option<Notification>>>,
+    ready: Notify,
+}
+
+impl NotificationQueue {
+    pub(crate) fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+            ready: Notify::new(),
+        }
+    }
+
+    /// Queues `item`. When full, the oldest entry is dropped and counted in
+    /// `pws_notifications_dropped_total`; unless a catch-up run is already
+    /// queued, `item` becomes one, so whatever the dropped entry would have
+    /// synced still is.
+    pub(crate) fn push(&self, item: Option<Notification>) {
+        let mut items = self.items.lock().unwrap();
+        let mut item = item;
+        if items.len() >= self.capacity {
+            items.pop_front();
+            METRICS
+                .notifications_dropped_total
+                .fetch_add(1, Ordering::Relaxed);
+            tracing::debug!("Notification queue full, dropped the oldest entry");
+            if !items.iter().any(Option::is_none) {
+                item = None;
+            }
+        }
+        items.push_back(item);
+        METRICS
+            .notification_queue_depth
+            .store(items.len() as u64, Ordering::Relaxed);
+        drop(items);
+        self.ready.notify_one();
+    }
+
+    /// Waits for the oldest entry. Meant for a single consumer.
+    pub(crate) async fn pop(&self) -> Option<Notification> {
+        loop {
+            {
+                let mut items = self.items.lock().unwrap();
+                if let Some(item) = items.pop_front() {
+                    METRICS
+                        .notification_queue_depth
+                        .store(items.len() as u64, Ordering::Relaxed);
+                    return item;
+                }
+            }
+            self.ready.notified().await;
+        }
+    }
+}
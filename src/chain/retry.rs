@@ -0,0 +1,142 @@
+//! Retries for block proposals that fail on transient RPC errors.
+//!
+//! A submission is retried only when its error looks transient (timeouts,
+//! dropped or refused connections, unavailable validators). Validation errors,
+//! such as insufficient funds or an operation rejected by the application, fail
+//! immediately since retrying can't change their outcome.
+//!
+//! A transient error doesn't tell whether the block was committed: the
+//! validators may have certified it before the connection dropped. Before each
+//! retry the chain is synchronized and its height compared to the height before
+//! the attempt. If it advanced, the operation may already have landed, so it is
+//! not resubmitted and [`SubmissionError::OutcomeUnknown`] is returned instead.
+//! This never double-submits, at the cost of occasionally asking the caller to
+//! check the chain when another block landed concurrently.
+
+use linera_base::data_types::BlockHeight;
+use linera_core::client::ChainClient;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::client::Environment;
+use crate::util::Backoff;
+
+/// Error messages that mark an error as transient
+const TRANSIENT_MARKERS: &[&str] = &[
+    "timed out",
+    "timeout",
+    "deadline exceeded",
+    "connection reset",
+    "connection refused",
+    "connection closed",
+    "broken pipe",
+    "unavailable",
+];
+
+/// How many times and how fast [`submit_with_retry`] retries
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first one
+    pub max_attempts: u32,
+    /// Pause before the first retry, doubled after each one, see [`Backoff`]
+    pub initial_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SubmissionError {
+    /// A transient error hit while the chain advanced, so the operation may
+    /// or may not have been committed
+    OutcomeUnknown { height: BlockHeight, reason: String },
+}
+
+impl std::fmt::Display for SubmissionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubmissionError::OutcomeUnknown { height, reason } => write!(
+                f,
+                "submission failed ({}) but the chain advanced past block {}; \
+                 check whether the operation landed before submitting it again",
+                reason, height
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SubmissionError {}
+
+/// Whether any cause of `error` looks like a transient network failure.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        let message = cause.to_string().to_ascii_lowercase();
+        TRANSIENT_MARKERS
+            .iter()
+            .any(|marker| message.contains(marker))
+    })
+}
+
+/// Runs `submit`, retrying on transient errors as long as the chain didn't
+/// advance in the meantime. See the module documentation for the semantics.
+///
+/// # Errors
+/// The first non-transient error, the last error once attempts run out, or
+/// [`SubmissionError::OutcomeUnknown`].
+pub(crate) async fn submit_with_retry<T, F, Fut>(
+    chain_client: &ChainClient<Environment>,
+    policy: RetryPolicy,
+    mut submit: F,
+) -> Result<T, anyhow::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, anyhow::Error>>,
+{
+    let chain_id = chain_client.chain_id();
+    let backoff = Backoff::new(policy.initial_delay, Duration::MAX);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let before = chain_client.chain_info().await?.next_block_height;
+
+        let error = match submit().await {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        if attempt >= policy.max_attempts || !is_transient(&error) {
+            return Err(error);
+        }
+
+        if let Err(e) = chain_client.synchronize_from_validators().await {
+            tracing::warn!(
+                "Failed to synchronize chain {} before retrying: {}",
+                chain_id,
+                e
+            );
+        }
+        if chain_client.chain_info().await?.next_block_height > before {
+            return Err(SubmissionError::OutcomeUnknown {
+                height: before,
+                reason: format!("{:#}", error),
+            }
+            .into());
+        }
+
+        let delay = backoff.delay(attempt - 1);
+        tracing::warn!(
+            "Transient error submitting on chain {} (attempt {}/{}), retrying in {:?}: {:#}",
+            chain_id,
+            attempt,
+            policy.max_attempts,
+            delay,
+            error
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
@@ -0,0 +1,218 @@
+//! Where [`Application::query`] gets its responses from: the network, the
+//! network with every exchange captured to disk, or a captured session.
+//!
+//! Recording with `--record <DIR>` and replaying with `--replay <DIR>` lets a
+//! parse failure or a bad diff be reproduced exactly, without a validator.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::debug;
+
+use super::{Application, QueryExecutor};
+use crate::logging::truncate_body;
+use crate::metrics::METRICS;
+
+/// Answers the GraphQL queries sent to an [`Application`].
+#[async_trait]
+pub trait QuerySource: Send + Sync {
+    async fn query(&self, app: &Application, query: &str) -> Result<String>;
+}
+
+/// Queries the application's service on its chain.
+pub struct Network;
+
+#[async_trait]
+impl QuerySource for Network {
+    async fn query(&self, app: &Application, query: &str) -> Result<String> {
+        app.query_network(query).await
+    }
+}
+
+/// Logs every query, the size of its response and how long it took at
+/// `debug`, and records the latency in the metrics.
+pub struct Traced {
+    inner: Arc<dyn QuerySource>,
+}
+
+impl Traced {
+    pub fn new(inner: Arc<dyn QuerySource>) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl QuerySource for Traced {
+    async fn query(&self, app: &Application, query: &str) -> Result<String> {
+        let started = Instant::now();
+        let result = self.inner.query(app, query).await;
+        let elapsed = started.elapsed();
+        METRICS.query_latency.observe(elapsed);
+        match &result {
+            Ok(response) => debug!(
+                "Query {} answered with {} bytes in {:?}",
+                truncate_body(query),
+                response.len(),
+                elapsed
+            ),
+            Err(e) => debug!(
+                "Query {} failed in {:?}: {}",
+                truncate_body(query),
+                elapsed,
+                e
+            ),
+        }
+        result
+    }
+}
+
+/// One captured query and the response it got, stored as `<seq>.json`
+#[derive(Serialize, Deserialize)]
+struct Exchange {
+    query: String,
+    response: String,
+}
+
+/// Queries the network and writes every exchange to `dir`, numbered in the
+/// order the responses arrived.
+pub struct Recorder {
+    dir: PathBuf,
+    next: AtomicU64,
+}
+
+impl Recorder {
+    /// # Errors
+    /// If `dir` can't be created.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create record directory {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            next: AtomicU64::new(0),
+        })
+    }
+
+    /// Writes `query` and its `response` as the next capture.
+    ///
+    /// # Errors
+    /// If the capture can't be written.
+    pub fn record(&self, query: &str, response: &str) -> Result<()> {
+        let seq = self.next.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{:06}.json", seq));
+        let exchange = Exchange {
+            query: query.to_string(),
+            response: response.to_string(),
+        };
+        let bytes = serde_json::to_vec_pretty(&exchange)?;
+        std::fs::write(&path, bytes).with_context(|| format!("Failed to record {}", path.display()))
+    }
+}
+
+#[async_trait]
+impl QuerySource for Recorder {
+    async fn query(&self, app: &Application, query: &str) -> Result<String> {
+        let response = app.query_network(query).await?;
+        // A capture that can't be written shouldn't take the watcher down.
+        if let Err(e) = self.record(query, &response) {
+            tracing::warn!("{:#}", e);
+        }
+        Ok(response)
+    }
+}
+
+/// Serves the responses captured by a [`Recorder`], without touching the
+/// network. Each query gets the responses recorded for the same query text,
+/// in their original order.
+pub struct Replayer {
+    responses: Mutex<HashMap<String, VecDeque<String>>>,
+}
+
+impl Replayer {
+    /// Loads every exchange recorded in `dir`.
+    ///
+    /// # Errors
+    /// If `dir` or one of its captures can't be read.
+    pub fn load(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        let mut paths = std::fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read replay directory {}", dir.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+        paths.sort();
+
+        let mut responses: HashMap<String, VecDeque<String>> = HashMap::new();
+        for path in paths {
+            let contents = std::fs::read_to_string(&path)?;
+            let exchange: Exchange = serde_json::from_str(&contents)
+                .with_context(|| format!("Invalid capture {}", path.display()))?;
+            responses
+                .entry(exchange.query)
+                .or_default()
+                .push_back(exchange.response);
+        }
+        Ok(Self {
+            responses: Mutex::new(responses),
+        })
+    }
+
+    /// The next recorded response to `query`.
+    ///
+    /// # Errors
+    /// If every response recorded for `query` was already served.
+    pub fn replay(&self, query: &str) -> Result<String> {
+        self.responses
+            .lock()
+            .unwrap()
+            .get_mut(query)
+            .and_then(VecDeque::pop_front)
+            .with_context(|| format!("No recorded response left for query {}", query))
+    }
+}
+
+#[async_trait]
+impl QuerySource for Replayer {
+    async fn query(&self, _app: &Application, query: &str) -> Result<String> {
+        self.replay(query)
+    }
+}
+
+/// Lets a captured session stand in for the application, e.g. to run the
+/// sync targets without a chain.
+#[async_trait]
+impl QueryExecutor for Replayer {
+    async fn query(&self, query: &str) -> Result<String> {
+        self.replay(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_session_replays_in_order() {
+        let dir = std::env::temp_dir().join(format!("pws-replay-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let recorder = Recorder::new(&dir).unwrap();
+        recorder.record("{ count }", "1").unwrap();
+        recorder.record("{ leaderboard }", "[]").unwrap();
+        recorder.record("{ count }", "2").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a capture").unwrap();
+
+        let replayer = Replayer::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(replayer.replay("{ count }").unwrap(), "1");
+        assert_eq!(replayer.replay("{ count }").unwrap(), "2");
+        assert_eq!(replayer.replay("{ leaderboard }").unwrap(), "[]");
+        assert!(replayer.replay("{ count }").is_err());
+        assert!(replayer.replay("{ unknown }").is_err());
+    }
+}
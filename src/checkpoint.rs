@@ -0,0 +1,61 @@
+//! The block height of each chain up to which the watcher synced every table,
+//! stored as JSON in `./checkpoints.json` ([`CHECKPOINT_PATH`]).
+
+use anyhow::{Context, Result};
+use linera_base::{data_types::BlockHeight, identifiers::ChainId};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Where the watcher records how far it has synced each chain
+pub const CHECKPOINT_PATH: &str = "./checkpoints.json";
+
+/// Per-chain block heights up to which every table was synced successfully,
+/// persisted as a small JSON file so restarts can skip chains that didn't advance.
+pub struct Checkpoints {
+    path: PathBuf,
+    heights: Mutex<HashMap<String, u64>>,
+}
+
+impl Checkpoints {
+    /// Loads the checkpoints at `path`. A missing or unreadable file starts
+    /// empty, which only means the next sync reprocesses everything.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let heights = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable checkpoints {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self {
+            path,
+            heights: Mutex::new(heights),
+        }
+    }
+
+    /// The next block height of `chain_id` at its last successful sync.
+    pub fn get(&self, chain_id: ChainId) -> Option<BlockHeight> {
+        let heights = self.heights.lock().unwrap();
+        heights.get(&chain_id.to_string()).copied().map(BlockHeight)
+    }
+
+    /// Records `height` for `chain_id` and writes the file.
+    ///
+    /// # Errors
+    /// If the file can't be written.
+    pub fn set(&self, chain_id: ChainId, height: BlockHeight) -> Result<()> {
+        let contents = {
+            let mut heights = self.heights.lock().unwrap();
+            heights.insert(chain_id.to_string(), height.0);
+            serde_json::to_string_pretty(&*heights)?
+        };
+
+        // Write then rename, so a crash never leaves a truncated file behind.
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, contents)
+            .and_then(|()| std::fs::rename(&tmp, &self.path))
+            .with_context(|| format!("Failed to write checkpoints {}", self.path.display()))
+    }
+}
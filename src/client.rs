@@ -21,14 +21,28 @@ key directly in memory and uses it to sign.
 
 use anyhow::Ok;
 use futures::lock::Mutex as AsyncMutex;
-use linera_base::{crypto::InMemorySigner, identifiers::ChainId};
+use linera_base::{
+    crypto::InMemorySigner,
+    data_types::{BlockHeight, Epoch},
+    identifiers::{ApplicationId, ChainId},
+};
+use linera_chain::{data_types::MessageBundle, types::ConfirmedBlockCertificate};
 use linera_client::{
     chain_listener::{ChainListener, ClientContext as _},
     util::wait_for_next_round,
 };
-use linera_core::{client::ListeningMode, JoinSetExt};
+use linera_core::{
+    client::{ChainClient, ListeningMode},
+    JoinSetExt,
+};
+use linera_execution::Message;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
+use crate::util::Backoff;
 use crate::{chain::Chain, storage::Storage, wallet::PersistentWallet};
 
 pub type Network = linera_rpc::node_provider::NodeProvider;
@@ -36,6 +50,263 @@ pub type Network = linera_rpc::node_provider::NodeProvider;
 pub type Environment =
     linera_core::environment::Impl<Storage, Network, InMemorySigner, linera_core::wallet::Memory>;
 
+/// Capacity of the [`ClientEvent`] channel; slow subscribers miss older events.
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// Attempts at synchronizing a chain from validators before giving up
+const SYNC_ATTEMPTS: u32 = 5;
+/// Pause before the first synchronization retry, backed off up to [`SYNC_MAX_DELAY`]
+const SYNC_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const SYNC_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Chain listener and validator connection tunables, exposed as CLI flags.
+/// The defaults are the listener's and the node provider's own.
+#[derive(clap::Args, Clone, Debug)]
+pub struct ListenerConfig {
+    /// Pause after a notification before the listener syncs the chain, so bursts are handled at once
+    #[arg(
+        long = "sync-delay-before-ms",
+        value_name = "MS",
+        default_value_t = 80,
+        global = true
+    )]
+    pub delay_before_ms: u64,
+
+    /// Pause after the listener synced a chain before handling its next notification
+    #[arg(
+        long = "sync-delay-after-ms",
+        value_name = "MS",
+        default_value_t = 80,
+        global = true
+    )]
+    pub delay_after_ms: u64,
+
+    /// How long to wait for a connection to a validator before giving up on it
+    #[arg(
+        long = "validator-connect-timeout",
+        value_name = "MS",
+        default_value_t = 4000,
+        global = true
+    )]
+    pub connect_timeout_ms: u64,
+
+    /// How long to wait for a connected validator to answer a request
+    #[arg(
+        long = "validator-read-timeout",
+        value_name = "MS",
+        default_value_t = 4000,
+        global = true
+    )]
+    pub read_timeout_ms: u64,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            delay_before_ms: 80,
+            delay_after_ms: 80,
+            connect_timeout_ms: 4000,
+            read_timeout_ms: 4000,
+        }
+    }
+}
+
+impl ListenerConfig {
+    /// Default client options with these listener settings applied, for [`Client::new`].
+    pub fn options(&self) -> linera_client::Options {
+        let mut options = linera_client::Options::default();
+        options.chain_listener_config.delay_before_ms = self.delay_before_ms;
+        options.chain_listener_config.delay_after_ms = self.delay_after_ms;
+        options.send_timeout = Duration::from_millis(self.connect_timeout_ms);
+        options.recv_timeout = Duration::from_millis(self.read_timeout_ms);
+        options
+    }
+}
+
+/// Error messages of a validator that couldn't be reached in time
+const CONNECT_TIMEOUT_MARKERS: &[&str] = &["error trying to connect", "connect timed out"];
+/// Error messages of a connected validator that didn't answer in time
+const READ_TIMEOUT_MARKERS: &[&str] = &["timeout expired", "deadline exceeded", "timed out"];
+
+/// The validator timeouts a [`Client`] was created with, to tell which one an
+/// error hit.
+#[derive(Clone, Copy, Debug)]
+pub struct ValidatorTimeouts {
+    pub connect: Duration,
+    pub read: Duration,
+}
+
+impl ValidatorTimeouts {
+    /// The [`ClientError::ConnectTimeout`] or [`ClientError::ReadTimeout`]
+    /// that `error` describes, if it is a timeout at all. Connection failures
+    /// are checked first since their messages also say "timed out".
+    pub fn classify(&self, error: &impl std::fmt::Display) -> Option<ClientError> {
+        let message = error.to_string();
+        let lower = message.to_lowercase();
+        if CONNECT_TIMEOUT_MARKERS.iter().any(|m| lower.contains(m)) {
+            Some(ClientError::ConnectTimeout {
+                after: self.connect,
+                error: message,
+            })
+        } else if READ_TIMEOUT_MARKERS.iter().any(|m| lower.contains(m)) {
+            Some(ClientError::ReadTimeout {
+                after: self.read,
+                error: message,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// `error` as a [`ClientError`] timeout when [`ValidatorTimeouts::classify`]
+    /// recognizes it, unchanged otherwise.
+    pub fn explain(&self, error: impl Into<anyhow::Error>) -> anyhow::Error {
+        let error = error.into();
+        match self.classify(&format!("{:#}", error)) {
+            Some(timeout) => timeout.into(),
+            None => error,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// Synchronizing from validators kept failing, usually because too few
+    /// of them answered to form a quorum
+    SyncQuorumUnavailable {
+        chain_id: ChainId,
+        attempts: u32,
+        /// Size of the committee, when it could be read from local storage
+        validators: Option<usize>,
+        last_error: String,
+    },
+    /// No connection to a validator within `--validator-connect-timeout`:
+    /// it is down or unreachable from here
+    ConnectTimeout { after: Duration, error: String },
+    /// A connected validator didn't answer within `--validator-read-timeout`:
+    /// it is likely overloaded
+    ReadTimeout { after: Duration, error: String },
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::SyncQuorumUnavailable {
+                chain_id,
+                attempts,
+                validators,
+                last_error,
+            } => {
+                write!(
+                    f,
+                    "could not synchronize chain {} from a quorum of ",
+                    chain_id
+                )?;
+                match validators {
+                    Some(validators) => write!(f, "its {} validators", validators)?,
+                    None => write!(f, "validators")?,
+                }
+                write!(f, " after {} attempts: {}", attempts, last_error)
+            }
+            ClientError::ConnectTimeout { after, error } => write!(
+                f,
+                "could not connect to a validator within {:?}, it may be unreachable: {}",
+                after, error
+            ),
+            ClientError::ReadTimeout { after, error } => write!(
+                f,
+                "a validator did not answer within {:?}, it may be overloaded: {}",
+                after, error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+/// Synchronizes `chain_client` from validators, retrying with exponential
+/// backoff so a single flaky validator doesn't fail startup. A read timeout
+/// doubles the pause once more, to give overloaded validators room.
+///
+/// # Errors
+/// [`ClientError::SyncQuorumUnavailable`] once all attempts failed, with the
+/// [`ClientError::ConnectTimeout`] or [`ClientError::ReadTimeout`] of the last
+/// attempt as its source when it timed out.
+pub(crate) async fn synchronize_with_retry(
+    chain_client: &ChainClient<Environment>,
+    timeouts: ValidatorTimeouts,
+) -> Result<(), anyhow::Error> {
+    let chain_id = chain_client.chain_id();
+    let backoff = Backoff::new(SYNC_INITIAL_DELAY, SYNC_MAX_DELAY);
+    // Backoff step, one ahead of the attempt after each read timeout
+    let mut step = 0;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let Err(error) = chain_client.synchronize_from_validators().await else {
+            return Ok(());
+        };
+        let timeout = timeouts.classify(&error);
+        if attempt >= SYNC_ATTEMPTS {
+            let validators = chain_client
+                .local_committee()
+                .await
+                .ok()
+                .map(|committee| committee.validators().len());
+            let unavailable = ClientError::SyncQuorumUnavailable {
+                chain_id,
+                attempts: attempt,
+                validators,
+                last_error: error.to_string(),
+            };
+            return Err(match timeout {
+                Some(timeout) => anyhow::Error::new(timeout).context(unavailable),
+                None => unavailable.into(),
+            });
+        }
+        if matches!(timeout, Some(ClientError::ReadTimeout { .. })) {
+            step += 1;
+        }
+        let delay = backoff.delay(step);
+        tracing::warn!(
+            "Synchronizing chain {} failed (attempt {}/{}), retrying in {:?}: {}",
+            chain_id,
+            attempt,
+            SYNC_ATTEMPTS,
+            delay,
+            timeout.map_or_else(|| error.to_string(), |t| t.to_string())
+        );
+        tokio::time::sleep(delay).await;
+        step += 1;
+    }
+}
+
+/// Events emitted by the client beyond the raw notification stream.
+#[derive(Clone, Debug)]
+pub enum ClientEvent {
+    /// `process_inbox` applied incoming messages to a chain.
+    InboxProcessed {
+        chain_id: ChainId,
+        messages: Vec<InboxMessage>,
+    },
+    /// The chain moved to a new epoch, so it was re-synchronized from the
+    /// new validator committee.
+    CommitteeChanged {
+        chain_id: ChainId,
+        previous: Epoch,
+        current: Epoch,
+    },
+}
+
+/// A message applied from a chain's inbox.
+#[derive(Clone, Debug)]
+pub struct InboxMessage {
+    pub origin: ChainId,
+    pub height: BlockHeight,
+    /// The receiving application, or `None` for system messages.
+    pub application_id: Option<ApplicationId>,
+}
+
 /// The full client API, exposed to the wallet implementation. Calls
 /// to this API can be trusted to have originated from the user's
 /// request.
@@ -48,19 +319,34 @@ pub struct Client {
     // hard-coded by `ChainListener`.
     pub client_context: Arc<AsyncMutex<linera_client::ClientContext<Environment>>>,
     pub persistent: PersistentWallet,
+    events: broadcast::Sender<ClientEvent>,
+    /// Stops the background chain listener
+    listener_cancel: CancellationToken,
+    listener: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
+    /// Validator timeouts from the options the client was created with
+    pub(crate) timeouts: ValidatorTimeouts,
 }
 
 impl Client {
     /// Creates a new client and connects to the network.
     ///
+    /// With `background_sync`, a [`ChainListener`] keeps the wallet's chains
+    /// synchronized until [`Client::shutdown`]. One-shot commands should
+    /// pass `false` so nothing keeps running after they finish.
+    ///
     /// # Errors
     /// On transport or protocol error, if persistent storage is
     /// unavailable, or if `options` is incorrectly structured.
     pub async fn new(
         w: &PersistentWallet,
         options: Option<linera_client::Options>,
+        background_sync: bool,
     ) -> Result<Client, anyhow::Error> {
         let options = options.unwrap_or_default();
+        let timeouts = ValidatorTimeouts {
+            connect: options.send_timeout,
+            read: options.recv_timeout,
+        };
 
         let mut storage = w.get_storage().await?;
         w.wallet
@@ -81,31 +367,89 @@ impl Client {
         // The `Arc` here is useless, but it is required by the `ChainListener` API.
         #[expect(clippy::arc_with_non_send_sync)]
         let client_context = Arc::new(AsyncMutex::new(client_context));
-        let client_clone = client_context.clone();
-        let chain_listener = ChainListener::new(
-            options.chain_listener_config,
-            client_clone,
-            storage,
-            tokio_util::sync::CancellationToken::new(),
-            tokio::sync::mpsc::unbounded_channel().1,
-        )
-        .run(true) // Enable background sync
-        .await?;
 
-        tokio::spawn(async move {
-            if let Err(error) = chain_listener.await {
-                println!("ChainListener error: {error:?}");
-            }
-        });
+        let listener_cancel = CancellationToken::new();
+        let mut listener = None;
+        if background_sync {
+            let client_clone = client_context.clone();
+            let chain_listener = ChainListener::new(
+                options.chain_listener_config,
+                client_clone,
+                storage,
+                listener_cancel.clone(),
+                tokio::sync::mpsc::unbounded_channel().1,
+            )
+            .run(true) // Enable background sync
+            .await?;
+
+            listener = Some(tokio::spawn(async move {
+                if let Err(error) = chain_listener.await {
+                    tracing::error!("ChainListener error: {error:?}");
+                }
+            }));
+        }
 
-        eprintln!("Linera Web client successfully initialized");
+        tracing::info!("Linera client initialized");
 
         Ok(Client {
             client_context,
             persistent: w.clone(),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            listener_cancel,
+            listener: Arc::new(std::sync::Mutex::new(listener)),
+            timeouts,
         })
     }
 
+    /// Stops the background [`ChainListener`], if any, and waits for it to exit.
+    pub async fn shutdown(&self) {
+        self.listener_cancel.cancel();
+        let listener = self.listener.lock().unwrap().take();
+        if let Some(listener) = listener {
+            let _ = listener.await;
+        }
+    }
+
+    /// Subscribes to [`ClientEvent`]s, such as messages applied by `process_inbox`.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ClientEvent> {
+        self.events.subscribe()
+    }
+
+    /// Publishes the incoming messages contained in the blocks produced by `process_inbox`.
+    pub(crate) fn report_inbox(
+        &self,
+        chain_id: ChainId,
+        certificates: &[ConfirmedBlockCertificate],
+    ) {
+        let messages: Vec<InboxMessage> = certificates
+            .iter()
+            .flat_map(|certificate| certificate.block().body.incoming_bundles())
+            .flat_map(|incoming| {
+                let MessageBundle {
+                    height, messages, ..
+                } = &incoming.bundle;
+                messages.iter().map(move |posted| InboxMessage {
+                    origin: incoming.origin,
+                    height: *height,
+                    application_id: match &posted.message {
+                        Message::User { application_id, .. } => Some(*application_id),
+                        Message::System(_) => None,
+                    },
+                })
+            })
+            .collect();
+
+        if !messages.is_empty() {
+            self.publish(ClientEvent::InboxProcessed { chain_id, messages });
+        }
+    }
+
+    /// Sends `event` to every [`Client::subscribe_events`] receiver.
+    pub(crate) fn publish(&self, event: ClientEvent) {
+        // No subscribers is not an error.
+        let _ = self.events.send(event);
+    }
+
     /// Connect to a chain on the Linera network.
     /// If no chain is provided, Default chain is used
     /// # Errors
@@ -116,8 +460,9 @@ impl Client {
         let chain_id = chain.unwrap_or_else(|| ctx.default_chain());
         let chain_client = ctx.make_chain_client(chain_id).await?;
 
-        chain_client.synchronize_from_validators().await?;
-        chain_client.process_inbox().await?;
+        synchronize_with_retry(&chain_client, self.timeouts).await?;
+        let (certificates, _) = chain_client.process_inbox().await?;
+        self.report_inbox(chain_id, &certificates);
 
         ctx.update_wallet(&chain_client).await?;
 
@@ -151,14 +496,15 @@ impl Client {
 
         ctx.chain_listeners.spawn_task(listener);
 
-        chain_client.synchronize_from_validators().await?;
+        synchronize_with_retry(&chain_client, self.timeouts).await?;
 
         loop {
-            let (_, maybe_timeout) = {
+            let (certificates, maybe_timeout) = {
                 let result = chain_client.process_inbox().await;
                 ctx.update_wallet_from_client(&chain_client).await?;
                 result?
             };
+            self.report_inbox(chain_id, &certificates);
             if maybe_timeout.is_some() {
                 wait_for_next_round(&mut notificiation_stream, maybe_timeout.unwrap()).await;
                 continue;
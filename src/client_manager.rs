@@ -1,4 +1,7 @@
-use linera_base::identifiers::ChainId;
+use crate::graphql;
+use crate::logging::truncate_body;
+use anyhow::{Context, Result};
+use linera_base::identifiers::{ApplicationId, ChainId};
 use std::{collections::HashMap, str::FromStr, sync::Arc};
 use tokio::sync::Mutex;
 
@@ -12,83 +15,100 @@ pub struct ChainClientManager {
     clients: Arc<Mutex<HashMap<ChainId, Arc<RunningChain>>>>,
 }
 
-/// A running instance of a [`Chain`](crate::chain::Chain) with cached state and application access.
-/// This wraps an active [`Chain`](crate::chain::Chain), maintains a per-chain cache,
-/// and runs background tasks that process notifications for that chain.
+/// A running instance of a [`Chain`](crate::chain::Chain) with application access.
+/// This wraps an active [`Chain`](crate::chain::Chain) and runs background tasks
+/// that process notifications for that chain.
+///
+/// Nothing is written to Supabase from here, so there is no diffing state to
+/// keep: only `watch` syncs, and its last written state lives in the
+/// [`SyncTarget`](crate::watcher::SyncTarget)s.
 pub struct RunningChain {
     pub(crate) chain: Chain,
     pub(crate) app: Application,
-
-    pub cache: Mutex<ChainStateCache>, // for our use case we keep a cache state, to avoid redundant Db updates
-}
-
-#[derive(Default)]
-pub struct ChainStateCache {
-    participants: HashMap<ChainId, String>,
-    tournament: HashMap<ChainId, String>,
-    matches: String,
-    // TODO: does not fit what we need
 }
 
 impl RunningChain {
     pub fn new(chain: Chain, app: Application) -> Self {
-        Self {
-            chain,
-            app,
-            cache: Mutex::new(ChainStateCache::default()),
-        }
+        Self { chain, app }
     }
 
-    /// this runs the notification service while querying and updating
+    /// Queries the application's notifications on every new block of the
+    /// chain and logs them at `debug`. A failed query is logged and retried
+    /// on the next block.
     pub fn start_background_task(self: &Arc<Self>) {
         let this = Arc::clone(self);
         self.chain.on_notification(move || {
             let this = Arc::clone(&this);
             async move {
-                match this
-                    .app
-                    .query(r#"{ "query": "query { notifications }" }"#)
-                    .await
-                {
-                    Ok(_value) => {
-                        todo!()
+                let chain_id = this.chain.chain_id();
+                match this.app.query(&graphql::notifications().to_json()).await {
+                    Ok(response) => {
+                        tracing::debug!(%chain_id, "Notifications: {}", truncate_body(&response))
                     }
-                    Err(_e) => eprintln!("query error on"),
+                    Err(e) => tracing::error!(%chain_id, "Notifications query failed: {:#}", e),
                 }
             }
         });
     }
 }
 
+/// Parses a chain id, as reported by the application or given on the command
+/// line, tolerating surrounding whitespace and upper-case hex.
+pub fn parse_chain_id(raw: &str) -> Result<ChainId> {
+    let normalized = raw.trim().to_ascii_lowercase();
+    ChainId::from_str(&normalized).with_context(|| format!("invalid chain id {:?}", raw))
+}
+
 impl ChainClientManager {
-    /// Convenience: caller doesn’t need the handle
-    pub async fn ensure_running(&self, chain_id: String, client: &Client, app_id: &str) {
-        if let Ok(chain_id) = ChainId::from_str(&chain_id) {
-            let _ = self.try_spawn_chain(chain_id, client, app_id).await;
-        }
+    /// The tournament chains currently tracked, in no particular order; also
+    /// listed by `/health` under `chain-service`.
+    pub async fn running_chains(&self) -> Vec<ChainId> {
+        self.clients.lock().await.keys().copied().collect()
+    }
+
+    /// Whether a [`RunningChain`] exists for `id`.
+    pub async fn is_running(&self, id: &ChainId) -> bool {
+        self.clients.lock().await.contains_key(id)
+    }
+
+    /// Parses `chain_id` and makes sure a [`RunningChain`] exists for it.
+    ///
+    /// # Errors
+    /// If the chain id is malformed or the chain could not be started.
+    pub async fn ensure_running(
+        &self,
+        chain_id: &str,
+        client: &Client,
+        app_id: ApplicationId,
+    ) -> Result<Arc<RunningChain>> {
+        let chain_id = parse_chain_id(chain_id)?;
+        self.try_spawn_chain(chain_id, client, app_id).await
     }
 
     pub async fn try_spawn_chain(
         &self,
         chain_id: ChainId,
         main_client: &Client,
-        app_id: &str,
-    ) -> Arc<RunningChain> {
+        app_id: ApplicationId,
+    ) -> Result<Arc<RunningChain>> {
         let mut map = self.clients.lock().await;
 
         if let Some(rc) = map.get(&chain_id) {
-            return rc.clone();
+            return Ok(rc.clone());
         }
 
         // First-time creation
-        let chain = main_client.assign_and_make_client(chain_id).await.unwrap();
-        let app = chain.application(app_id).await.unwrap();
+        let chain = main_client
+            .assign_and_make_client(chain_id)
+            .await
+            .with_context(|| format!("failed to start chain {chain_id}"))?;
+        let app = chain.application(app_id);
 
         let running = Arc::new(RunningChain::new(chain, app));
         running.start_background_task(); // handle notification
         map.insert(chain_id, running.clone());
 
-        println!("Started background task for chain: {chain_id}");
-        running
+        tracing::info!(%chain_id, %app_id, "Started background task for chain");
+        Ok(running)
     }
 }
@@ -0,0 +1,131 @@
+//! `doctor`: checks every dependency of the service in turn, so a failure can
+//! be pinned on the faucet, storage, Supabase or the application.
+
+use anyhow::Result;
+use linera_base::identifiers::{ApplicationId, ChainId};
+use std::future::Future;
+use std::path::PathBuf;
+
+use crate::client::Client;
+use crate::logging::{mark_err, mark_ok};
+use crate::models::GameCount;
+use crate::storage::{self, StorageConfig};
+use crate::supabase::{SupabaseClient, SupabaseConfig};
+use crate::wallet::{fetch_genesis_config, FaucetConfig, PersistentWallet};
+
+/// What `doctor` needs from the command line
+pub struct DoctorArgs {
+    pub keystore_path: Option<PathBuf>,
+    pub storage: StorageConfig,
+    pub faucets: FaucetConfig,
+    pub supabase: SupabaseConfig,
+    pub chain_id: Option<ChainId>,
+    pub app_id: Option<ApplicationId>,
+}
+
+/// Runs one check and prints its outcome, with `hint` on failure.
+async fn check<T>(name: &str, hint: &str, fut: impl Future<Output = Result<T>>) -> Option<T> {
+    match fut.await {
+        Ok(value) => {
+            println!("{} {}", mark_ok(), name);
+            Some(value)
+        }
+        Err(e) => {
+            println!("{} {}: {:#}", mark_err(), name, e);
+            println!("    hint: {}", hint);
+            None
+        }
+    }
+}
+
+/// Runs all checks, stopping short of those that depend on a failed one.
+///
+/// # Errors
+/// If any check failed.
+pub async fn run(args: DoctorArgs) -> Result<()> {
+    let mut failures = 0;
+
+    // Fallback faucets only need one of them to work.
+    let mut faucet_up = false;
+    for url in &args.faucets.faucet_urls {
+        let reachable = check(
+            &format!("faucet {} reachable", url),
+            &format!("is a faucet running at {}?", url),
+            async { Ok(reqwest::get(url).await?) },
+        )
+        .await
+        .is_some();
+        if reachable {
+            let genesis = check(
+                &format!("genesis config fetched from {}", url),
+                "the faucet answered but not as a Linera faucet, check the URL and its version",
+                fetch_genesis_config(url, args.faucets.timeout()),
+            )
+            .await;
+            faucet_up |= genesis.is_some();
+        }
+    }
+    failures += usize::from(!faucet_up);
+
+    // Opened and dropped again, so the application check below can open it.
+    let storage_ok = check(
+        "storage openable",
+        "make sure no other instance is running, or see the storage flags in the README",
+        storage::connect(&args.storage),
+    )
+    .await
+    .is_some();
+    failures += usize::from(!storage_ok);
+
+    let retries = args.supabase.retries;
+    let supabase = check(
+        "Supabase reachable with valid credentials",
+        "set SUPABASE_URL and SUPABASE_KEY, in the environment or .env",
+        async {
+            let client = SupabaseClient::with_config(args.supabase)?;
+            client.count::<GameCount>(&[]).await
+        },
+    )
+    .await;
+    if supabase.is_some() {
+        println!("    retries on gateway errors: {}", retries);
+    }
+    failures += usize::from(supabase.is_none());
+
+    if let Some(app_id) = args.app_id {
+        let responds = if faucet_up && storage_ok {
+            check(
+                &format!("application {} responds", app_id),
+                "check the application id and that it is deployed on the chain",
+                async {
+                    let wallet =
+                        PersistentWallet::new(args.keystore_path, &args.storage, &args.faucets)
+                            .await?;
+                    let client = Client::new(&wallet, None, false).await?;
+                    client
+                        .chain(args.chain_id)
+                        .await?
+                        .application(app_id)
+                        .ping()
+                        .await
+                },
+            )
+            .await
+            .is_some()
+        } else {
+            println!(
+                "{} application {} responds: skipped, fix the checks above first",
+                mark_err(),
+                app_id
+            );
+            false
+        };
+        failures += usize::from(!responds);
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} check(s) failed", failures);
+    }
+    println!("All checks passed");
+    Ok(())
+}
@@ -0,0 +1,126 @@
+//! Every GraphQL request sent to the application's service, built in one
+//! place. Arguments go through [`string`], which escapes them as GraphQL
+//! literals, and request bodies are serialized by `serde_json`, so nothing is
+//! escaped by hand.
+
+use crate::models::tournament::Micros;
+
+/// Fields read of every tournament
+const TOURNAMENT_FIELDS: &str = "organiserChain organiserId organiserName tournamentId tournamentName tournamentFormat matchType gameMode timeControl { baseMinutes incrementSeconds modeLabel } bannerImageUrl sponsorLogoUrl maxPlayers minPlayers startingTime endTime prizeType prizePoolDescription prizePool visibility customTags version createdAt updatedAt status";
+/// Fields read of every tournament participant
+const PARTICIPANT_FIELDS: &str = "id player { name elo matches ath }";
+
+/// A GraphQL request, sent as `{ "query": ... }`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Request {
+    document: String,
+}
+
+impl Request {
+    /// An anonymous query of `selection`, which
+    /// [`Application::query_batch`](crate::chain::Application::query_batch)
+    /// can merge with others.
+    pub fn query(selection: &str) -> Self {
+        Self {
+            document: format!("query {{ {} }}", selection),
+        }
+    }
+
+    /// An anonymous mutation of `selection`.
+    pub fn mutation(selection: &str) -> Self {
+        Self {
+            document: format!("mutation {{ {} }}", selection),
+        }
+    }
+
+    /// A request sending `document` unchanged, e.g. one given on the command line.
+    pub fn raw(document: impl Into<String>) -> Self {
+        Self {
+            document: document.into(),
+        }
+    }
+
+    /// The JSON body sent to the service.
+    pub fn to_json(&self) -> String {
+        serde_json::json!({ "query": self.document }).to_string()
+    }
+}
+
+/// `value` as a GraphQL string literal, quoted and escaped. JSON string
+/// escapes are valid in GraphQL.
+pub fn string(value: &str) -> String {
+    serde_json::Value::from(value).to_string()
+}
+
+/// Answered by every GraphQL service, whatever its schema.
+pub fn ping() -> Request {
+    Request::query("__typename")
+}
+
+/// Subscribes the chain to the application's events.
+pub fn subscribe() -> Request {
+    Request::mutation("subscribe")
+}
+
+pub fn tournament_chains() -> Request {
+    Request::query("tournamentChains")
+}
+
+pub fn notifications() -> Request {
+    Request::query("notifications")
+}
+
+/// All tournaments, or with `since` only those starting at or after it, for
+/// services whose `allTournaments` takes a `since` argument.
+pub fn tournaments(since: Option<Micros>) -> Request {
+    match since {
+        Some(since) => Request::query(&format!(
+            "allTournaments(since: {}) {{ {} }}",
+            since, TOURNAMENT_FIELDS
+        )),
+        None => Request::query(&format!("allTournaments {{ {} }}", TOURNAMENT_FIELDS)),
+    }
+}
+
+/// The participants of a tournament.
+pub fn participants(tournament_id: &str) -> Request {
+    Request::query(&format!(
+        "participants(tournamentId: {}) {{ {} }}",
+        string(tournament_id),
+        PARTICIPANT_FIELDS
+    ))
+}
+
+/// One page of [`participants`], `limit` participants from `offset` on.
+pub fn participants_page(tournament_id: &str, limit: usize, offset: usize) -> Request {
+    Request::query(&format!(
+        "participants(tournamentId: {}, limit: {}, offset: {}) {{ {} }}",
+        string(tournament_id),
+        limit,
+        offset,
+        PARTICIPANT_FIELDS
+    ))
+}
+
+/// The encoded bracket of a tournament, see
+/// [`BracketData`](crate::models::participants::BracketData).
+pub fn bracket(tournament_id: &str) -> Request {
+    Request::query(&format!(
+        "encodedParticipants(tournamentId: {})",
+        string(tournament_id)
+    ))
+}
+
+pub fn leaderboard() -> Request {
+    Request::query("leaderboard { elo id name matches won lost }")
+}
+
+/// The number of games played.
+pub fn count() -> Request {
+    Request::query("count")
+}
+
+/// The most recent match.
+pub fn match_history_last() -> Request {
+    Request::query("matchHistoryLast { you { id name } opponent { id name } blobHash }")
+}
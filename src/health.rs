@@ -0,0 +1,66 @@
+//! Progress tracking for the background sync: when notifications last arrived
+//! and when a sync last went through, behind `/health` and `--max-notification-lag`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+
+/// Unix time of the last notification handled, 0 if none yet
+static LAST_NOTIFICATION: AtomicU64 = AtomicU64::new(0);
+/// Unix time of the last sync that completed without errors, or of startup
+static LAST_SUCCESS: AtomicU64 = AtomicU64::new(0);
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Records that a notification arrived and a sync is about to run.
+pub fn record_notification() {
+    LAST_NOTIFICATION.store(now(), Ordering::Relaxed);
+}
+
+/// Records that a sync completed and every write went through.
+pub fn record_success() {
+    LAST_SUCCESS.store(now(), Ordering::Relaxed);
+}
+
+/// How long notifications have been arriving without a successful sync.
+///
+/// Zero while caught up, so a quiet chain never counts as lagging.
+pub fn lag() -> Duration {
+    let last_success = LAST_SUCCESS.load(Ordering::Relaxed);
+    if LAST_NOTIFICATION.load(Ordering::Relaxed) <= last_success {
+        return Duration::ZERO;
+    }
+    Duration::from_secs(now().saturating_sub(last_success))
+}
+
+pub fn is_healthy(max_lag: Duration) -> bool {
+    lag() <= max_lag
+}
+
+/// Finishes once [`lag`] exceeds `max_lag`. `main` then shuts down and exits
+/// non-zero, so an orchestrator can restart a watcher that stopped making progress.
+pub fn spawn_lag_guard(max_lag: Duration) -> JoinHandle<()> {
+    // Startup counts as a success, otherwise the first notification would
+    // already be "lagging" since the epoch.
+    let _ = LAST_SUCCESS.compare_exchange(0, now(), Ordering::Relaxed, Ordering::Relaxed);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval((max_lag / 4).max(Duration::from_secs(1)));
+        loop {
+            ticker.tick().await;
+            if !is_healthy(max_lag) {
+                tracing::error!(
+                    "No successful sync for {:?} despite new notifications (max {:?}), shutting down",
+                    lag(),
+                    max_lag
+                );
+                return;
+            }
+        }
+    })
+}
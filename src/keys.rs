@@ -0,0 +1,101 @@
+//! `keys`: rotates the key signing for the default chain.
+//!
+//! A rotation never leaves the chain without a usable key. The new key is
+//! saved to the keystore before it becomes an owner, and is added next to the
+//! current key rather than replacing it, so blocks signed by either key are
+//! accepted. Instances still running with the previous key keep working until
+//! it is revoked, either right away with `--revoke-previous` or later with
+//! `keys revoke`, once every instance signs with the new key.
+
+use anyhow::Result;
+use linera_base::identifiers::AccountOwner;
+
+use crate::chain::{AddOwnerOptions, Chain};
+use crate::logging::{mark_ok, mark_warn};
+use crate::wallet::PersistentWallet;
+
+/// What `keys rotate` needs from the command line
+pub struct RotateArgs {
+    /// Weight of the new key among the chain owners
+    pub weight: u64,
+    /// Sign for the default chain with the new key from the next start
+    pub make_default: bool,
+    /// Remove the previous key from the chain owners; needs `make_default`
+    pub revoke_previous: bool,
+}
+
+/// Adds a new key to the keystore and to the owners of the default chain.
+///
+/// # Errors
+/// If the keystore or wallet can't be written, or an ownership change is
+/// rejected. A key saved or added before the failure stays, so running it
+/// again adds another key rather than picking up where it stopped.
+pub async fn rotate(chain: &Chain, wallet: &mut PersistentWallet, args: RotateArgs) -> Result<()> {
+    let previous = wallet.signer_address();
+    let owner = wallet.generate_key()?;
+    println!("{} Saved new key {} to the keystore", mark_ok(), owner);
+
+    let hash = chain
+        .add_owner(
+            owner,
+            AddOwnerOptions {
+                weight: args.weight,
+            },
+        )
+        .await?;
+    println!(
+        "{} Added {} to the owners of chain {} in block {}",
+        mark_ok(),
+        owner,
+        chain.chain_id(),
+        hash
+    );
+
+    if !args.make_default {
+        println!(
+            "{} Still signing with {}; both keys own the chain",
+            mark_warn(),
+            previous
+        );
+        return Ok(());
+    }
+    wallet.set_default_owner(owner)?;
+    println!("{} Signing with {} from the next start", mark_ok(), owner);
+
+    if args.revoke_previous {
+        // Signed with the previous key, which this process still uses.
+        let hash = chain.remove_owner(previous).await?;
+        println!("{} Revoked {} in block {}", mark_ok(), previous, hash);
+    } else {
+        println!(
+            "{} Both keys own the chain until `keys revoke --owner {}`",
+            mark_warn(),
+            previous
+        );
+    }
+    Ok(())
+}
+
+/// Removes `owner` from the owners of the default chain, ending the window
+/// where both keys of a rotation are valid.
+///
+/// # Errors
+/// If `owner` is the key this wallet signs with, or the ownership change is
+/// rejected.
+pub async fn revoke(chain: &Chain, wallet: &PersistentWallet, owner: AccountOwner) -> Result<()> {
+    if owner == wallet.signer_address() {
+        anyhow::bail!(
+            "{} is the key this wallet signs with, rotate with --make-default first",
+            owner
+        );
+    }
+    let hash = chain.remove_owner(owner).await?;
+    println!(
+        "{} Revoked {} from chain {} in block {}",
+        mark_ok(),
+        owner,
+        chain.chain_id(),
+        hash
+    );
+    Ok(())
+}
@@ -0,0 +1,41 @@
+//! Bounds on what untrusted peers can make the service hold in memory.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Largest response read into memory, set once at startup from `--max-response-bytes`
+static MAX_RESPONSE_BYTES: AtomicUsize = AtomicUsize::new(16 * 1024 * 1024);
+
+pub fn set_max_response_bytes(max: usize) {
+    MAX_RESPONSE_BYTES.store(max, Ordering::Relaxed);
+}
+
+pub fn max_response_bytes() -> usize {
+    MAX_RESPONSE_BYTES.load(Ordering::Relaxed)
+}
+
+/// A response exceeded `--max-response-bytes` and was dropped
+#[derive(Debug)]
+pub struct ResponseTooLarge {
+    pub limit: usize,
+}
+
+impl std::fmt::Display for ResponseTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "response larger than {} bytes, see --max-response-bytes",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for ResponseTooLarge {}
+
+/// Fails with [`ResponseTooLarge`] if `len` bytes exceed the cap.
+pub fn check_response_size(len: usize) -> Result<(), ResponseTooLarge> {
+    let limit = max_response_bytes();
+    if len > limit {
+        return Err(ResponseTooLarge { limit });
+    }
+    Ok(())
+}
@@ -0,0 +1,281 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use anyhow::Context;
+use linera_base::identifiers::AccountOwner;
+use tracing::level_filters::LevelFilter;
+use tracing::{Event, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::format::{DefaultFields, Format, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// When console output uses ANSI colors and decorative emoji
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorMode {
+    Never,
+    /// Only when stdout is a terminal
+    #[default]
+    Auto,
+    Always,
+}
+
+impl ColorMode {
+    fn enabled(self) -> bool {
+        match self {
+            ColorMode::Never => false,
+            ColorMode::Auto => std::io::stdout().is_terminal(),
+            ColorMode::Always => true,
+        }
+    }
+}
+
+/// When `--log-file` starts a new file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    /// Keep appending to a single file
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// Where logs are written besides stdout, exposed as CLI flags
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct LogFileConfig {
+    /// Also write logs to this file, rotated per `--log-rotation`; rotated files get a date suffix
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// How often `--log-file` starts a new file
+    #[arg(long, value_enum, default_value_t, global = true)]
+    pub log_rotation: LogRotation,
+
+    /// Rotated log files kept, the oldest deleted beyond that (0 keeps all)
+    #[arg(long, value_name = "N", default_value_t = 0, global = true)]
+    pub log_max_files: usize,
+
+    /// Write logs only to `--log-file`, not to stdout
+    #[arg(long, requires = "log_file", global = true)]
+    pub log_file_only: bool,
+}
+
+impl LogFileConfig {
+    /// The appender writing to `--log-file`, if set.
+    fn appender(&self) -> anyhow::Result<Option<RollingFileAppender>> {
+        let Some(path) = &self.log_file else {
+            return Ok(None);
+        };
+        let file_name = path
+            .file_name()
+            .with_context(|| format!("--log-file {} has no file name", path.display()))?;
+        let directory = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| std::path::Path::new("."));
+
+        let mut builder = RollingFileAppender::builder()
+            .rotation(self.log_rotation.into())
+            .filename_prefix(file_name.to_string_lossy());
+        if self.log_max_files > 0 {
+            builder = builder.max_log_files(self.log_max_files);
+        }
+        let appender = builder
+            .build(directory)
+            .with_context(|| format!("Failed to open log file {}", path.display()))?;
+        Ok(Some(appender))
+    }
+}
+
+/// Whether Unicode symbols are printed, decided at startup by [`ColorMode`]
+/// and `--no-emoji`
+static DECORATE: AtomicBool = AtomicBool::new(true);
+
+/// `symbol` when decorations are on, otherwise nothing. For purely decorative
+/// emoji; status markers carry meaning and have ASCII fallbacks instead, see
+/// [`mark_ok`].
+pub fn emoji(symbol: &'static str) -> &'static str {
+    if DECORATE.load(Ordering::Relaxed) {
+        symbol
+    } else {
+        ""
+    }
+}
+
+/// `symbol`, or `ascii` when decorations are off.
+fn mark(symbol: &'static str, ascii: &'static str) -> &'static str {
+    if DECORATE.load(Ordering::Relaxed) {
+        symbol
+    } else {
+        ascii
+    }
+}
+
+/// Prefix of success messages: `✓`, or `[OK]` in ASCII-only output.
+pub fn mark_ok() -> &'static str {
+    mark("✓", "[OK]")
+}
+
+/// Prefix of error messages: `✗`, or `[ERR]` in ASCII-only output.
+pub fn mark_err() -> &'static str {
+    mark("✗", "[ERR]")
+}
+
+/// Prefix of warnings: `⚠`, or `[WARN]` in ASCII-only output.
+pub fn mark_warn() -> &'static str {
+    mark("⚠", "[WARN]")
+}
+
+/// Longest response body included in a log line or error, in bytes
+static MAX_BODY_LOG_BYTES: AtomicUsize = AtomicUsize::new(2048);
+
+pub fn set_max_body_log_bytes(max: usize) {
+    MAX_BODY_LOG_BYTES.store(max, Ordering::Relaxed);
+}
+
+/// Cuts `body` to the `--max-body-log-bytes` cap, on a character boundary,
+/// so large payloads don't produce unbounded log lines.
+pub fn truncate_body(body: &str) -> Cow<'_, str> {
+    let max = MAX_BODY_LOG_BYTES.load(Ordering::Relaxed);
+    if body.len() <= max {
+        return Cow::Borrowed(body);
+    }
+    let mut end = max;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!("{}...(truncated)", &body[..end]))
+}
+
+/// Abbreviates an owner id such as `0x1234…abcd` (`0x1234...abcd` in
+/// ASCII-only output) for logs; stored data keeps the full value. Short or
+/// non-hex ids are returned unchanged.
+pub fn short_id(id: &str) -> String {
+    let (prefix, hex) = match id.strip_prefix("0x") {
+        Some(hex) => ("0x", hex),
+        None => ("", id),
+    };
+    if hex.len() <= 12 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return id.to_string();
+    }
+    format!(
+        "{}{}{}{}",
+        prefix,
+        &hex[..4],
+        mark("…", "..."),
+        &hex[hex.len() - 4..]
+    )
+}
+
+/// [`short_id`] of an [`AccountOwner`].
+pub fn short_owner(owner: &AccountOwner) -> String {
+    short_id(&owner.to_string())
+}
+
+/// Prefixes every log line with `instance_id=<id>` so several watchers can
+/// share one log sink.
+struct WithInstanceId<F> {
+    inner: F,
+    instance_id: String,
+}
+
+impl<S, N, F> FormatEvent<S, N> for WithInstanceId<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        write!(writer, "instance_id={} ", self.instance_id)?;
+        self.inner.format_event(ctx, writer, event)
+    }
+}
+
+/// Installs the global subscriber. `RUST_LOG` takes precedence; otherwise
+/// `info` is shown, or only warnings and errors with `quiet`. `color` decides
+/// both ANSI colors and Unicode symbols, unless `no_emoji` forces ASCII.
+///
+/// With a `--log-file`, lines are also written there, timestamped and
+/// without colors, by a background thread. The returned guard flushes it
+/// when dropped, so it must be held until the process exits.
+///
+/// # Errors
+/// If the log file can't be opened.
+pub fn init_logging(
+    instance_id: &str,
+    quiet: bool,
+    color: ColorMode,
+    no_emoji: bool,
+    file: &LogFileConfig,
+) -> anyhow::Result<Option<WorkerGuard>> {
+    let color = color.enabled();
+    DECORATE.store(color && !no_emoji, Ordering::Relaxed);
+
+    let default_level = if quiet {
+        LevelFilter::WARN
+    } else {
+        LevelFilter::INFO
+    };
+
+    let format = WithInstanceId {
+        inner: Format::default().with_target(true).without_time(), // show targets, optional timestamps
+        instance_id: instance_id.to_string(),
+    };
+    let stdout_layer = (!file.log_file_only).then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(color)
+            .fmt_fields(DefaultFields::new())
+            .event_format(format)
+    });
+
+    let (file_layer, guard) = match file.appender()? {
+        Some(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let format = WithInstanceId {
+                // A file is read later, so its lines need timestamps
+                inner: Format::default().with_target(true),
+                instance_id: instance_id.to_string(),
+            };
+            let layer = tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(writer)
+                .fmt_fields(DefaultFields::new())
+                .event_format(format);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::Registry::default()
+        .with(stdout_layer)
+        .with(file_layer)
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(default_level.into())
+                .from_env_lossy(), // reads RUST_LOG
+        )
+        .init();
+    Ok(guard)
+}
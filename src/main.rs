@@ -1,36 +1,57 @@
 #![recursion_limit = "256"]
 #![allow(dead_code)]
 
-use crate::supabase::{SupabaseClient, SupabaseModel};
-use crate::{client::Client, wallet::PersistentWallet};
+use crate::chain::source::{Network, QuerySource, Recorder, Replayer, Traced};
+use crate::chain::{SubscriptionOptions, TransferParams};
+use crate::client::{Client, ListenerConfig};
+use crate::parse::parse_response;
+use crate::supabase::{SupabaseClient, SupabaseConfig};
+use crate::wallet::{FaucetConfig, PersistentWallet};
+pub mod build_info;
 pub mod chain;
+pub mod checkpoint;
 pub mod client;
 pub mod client_manager;
+pub mod doctor;
+pub mod graphql;
+pub mod health;
+pub mod keys;
+pub mod limits;
+pub mod logging;
+pub mod metrics;
 pub mod models;
+pub mod parse;
 pub mod resource;
+pub mod schema;
+pub mod secret;
+pub mod server;
+pub mod shutdown;
 pub mod storage;
 pub mod supabase;
+pub mod util;
 pub mod wallet;
+pub mod watcher;
+use crate::doctor::DoctorArgs;
+use crate::keys::RotateArgs;
+use crate::logging::{init_logging, mark_ok, ColorMode, LogFileConfig};
 use crate::resource::start_resource_logger;
+use crate::schema::EnsureSchemaArgs;
+use crate::storage::StorageConfig;
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
-use client_manager::ChainClientManager;
-use models::tournament::{
-    participants_query, ParticipantResponse, Tournament, TournamentParticipant, TournamentResponse,
-    QUERY_TOURNAMENTS,
-};
-use models::{
-    CountResponse, GameCount, LeaderBoardResponse, Leaderboard, MatchHistory, MatchHistoryDB,
-    MatchHistoryResponse,
-};
+use client_manager::{parse_chain_id, ChainClientManager};
+use linera_base::data_types::BlockHeight;
+use linera_base::identifiers::{Account, AccountOwner, ApplicationId, ChainId};
+use linera_core::worker::{Notification, Reason};
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing_subscriber::layer::SubscriberExt;
-use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::{fmt, EnvFilter};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use watcher::{Output, Table, WatchConfig, Watcher, WritePolicy};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -42,9 +63,103 @@ struct Args {
     #[arg(long = "with-keystore", value_name = "PATH", global = true)]
     keystore_path: Option<PathBuf>,
 
-    #[arg(long)]
+    /// Periodically print resource usage and service metrics
+    #[arg(long, global = true)]
     metrics: bool,
 
+    /// Fail on response fields the response structs don't declare, instead of ignoring them
+    #[arg(long, global = true)]
+    strict_schema: bool,
+
+    /// Label attached to every log line and metric, defaults to the hostname
+    #[arg(long, value_name = "ID", global = true)]
+    instance_id: Option<String>,
+
+    /// Use ANSI colors and Unicode symbols: `auto` only when stdout is a terminal
+    #[arg(long, value_enum, default_value_t, global = true)]
+    color: ColorMode,
+
+    /// Print ASCII markers such as `[OK]` and `[ERR]` instead of symbols and emoji, even on a terminal
+    #[arg(long, global = true)]
+    no_emoji: bool,
+
+    /// Only log warnings and errors (`RUST_LOG` still takes precedence)
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    #[command(flatten)]
+    log_file: LogFileConfig,
+
+    /// Longest response body, in bytes, included in log lines and errors
+    #[arg(long, value_name = "BYTES", default_value_t = 2048, global = true)]
+    max_body_log_bytes: usize,
+
+    /// Largest application or Supabase response read into memory; bigger ones fail
+    #[arg(long, value_name = "BYTES", default_value_t = 16 * 1024 * 1024, global = true)]
+    max_response_bytes: usize,
+
+    /// Chain to operate on instead of the wallet's default chain
+    #[arg(long = "chain-id", value_name = "CHAIN_ID", value_parser = parse_chain_id, global = true)]
+    chain_id: Option<ChainId>,
+
+    /// Write every application query and its response to this directory
+    #[arg(long, value_name = "DIR", global = true)]
+    record: Option<PathBuf>,
+
+    /// Log every application query with its response size and latency at `debug`
+    #[arg(long, global = true)]
+    trace_queries: bool,
+
+    /// Answer application queries from a directory written by `--record` instead of the network
+    #[arg(long, value_name = "DIR", global = true, conflicts_with = "record")]
+    replay: Option<PathBuf>,
+
+    #[command(flatten)]
+    supabase: SupabaseConfig,
+
+    #[command(flatten)]
+    storage: StorageConfig,
+
+    #[command(flatten)]
+    faucets: FaucetConfig,
+
+    #[command(flatten)]
+    listener: ListenerConfig,
+
+    /// Resubscribe to notifications when none arrive for this many seconds (0 disables)
+    #[arg(
+        long = "idle-timeout",
+        value_name = "SECS",
+        default_value_t = 0,
+        global = true
+    )]
+    idle_timeout_secs: u64,
+
+    /// Stop and exit with an error after this many failed resubscriptions in a row (0 retries forever)
+    #[arg(long, value_name = "N", default_value_t = 0, global = true)]
+    reconnect_max_attempts: u32,
+
+    /// Fraction of every retry and reconnection delay that is randomized, from 0 to 1
+    #[arg(long, value_name = "FRACTION", default_value_t = util::DEFAULT_JITTER, value_parser = parse_fraction, global = true)]
+    backoff_jitter: f64,
+
+    /// Notifications queued while a sync runs; beyond that the oldest are dropped and counted
+    #[arg(long, value_name = "N", default_value_t = 64, global = true)]
+    notification_queue_depth: usize,
+
+    /// Exit unhealthy when notifications keep arriving but no sync succeeded for this many seconds (0 disables)
+    #[arg(
+        long = "max-notification-lag",
+        value_name = "SECS",
+        default_value_t = 300,
+        global = true
+    )]
+    max_notification_lag_secs: u64,
+
+    /// Serve `/health` and `/metrics` over HTTP on this address, e.g. `0.0.0.0:9090`
+    #[arg(long, value_name = "ADDR", global = true)]
+    http_addr: Option<SocketAddr>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -52,6 +167,27 @@ struct Args {
 #[derive(Subcommand, Debug)]
 enum Commands {
     Metrics,
+    /// Print the version; with `--verbose`, also the commit, build date, rustc and linera versions
+    Version {
+        #[arg(long)]
+        verbose: bool,
+    },
+    /// Check the faucet, storage, Supabase and optionally an application, one by one
+    Doctor {
+        /// Also check that this application responds to queries
+        #[arg(long, value_name = "APP_ID", value_parser = parse_application_id)]
+        app_id: Option<ApplicationId>,
+    },
+    /// List the Supabase tables that are missing; with `--apply`, create them
+    EnsureSchema {
+        /// Create the missing tables; without it nothing in the database is changed
+        #[arg(long)]
+        apply: bool,
+
+        /// Postgres function taking a `sql` argument and executing it, see the README
+        #[arg(long, value_name = "NAME", default_value = "exec_sql")]
+        sql_function: String,
+    },
     /// Deploy an application and run as server
     Deploy {
         /// Path to the project directory containing the contract and service WASM files
@@ -61,22 +197,202 @@ enum Commands {
         /// JSON-encoded initialization arguments for the application
         #[arg(long = "json-argument", value_name = "JSON")]
         json_argument: Option<String>,
+
+        /// File containing the JSON-encoded initialization arguments
+        #[arg(
+            long = "json-argument-file",
+            value_name = "PATH",
+            conflicts_with = "json_argument"
+        )]
+        json_argument_file: Option<PathBuf>,
     },
 
     /// Subscribe and watch an existing application
     Watch {
         /// Application ID to subscribe to
-        #[arg(long, value_name = "APP_ID")]
-        app_id: String,
+        #[arg(long, value_name = "APP_ID", value_parser = parse_application_id)]
+        app_id: ApplicationId,
+
+        /// Buffer upserts and flush them to Supabase on this interval instead of writing immediately
+        #[arg(long = "flush-interval-ms", value_name = "MS")]
+        flush_interval_ms: Option<u64>,
+
+        /// Number of buffered rows per table that triggers an early flush
+        #[arg(long, value_name = "ROWS", default_value_t = 100)]
+        flush_threshold: usize,
+
+        /// Comma-separated tables to sync; the others are neither queried nor written
+        #[arg(long, value_enum, value_delimiter = ',', default_values_t = Table::DEFAULT)]
+        tables: Vec<Table>,
+
+        /// Reprocess from this block height, ignoring the saved checkpoint
+        #[arg(long, value_name = "HEIGHT")]
+        from_block: Option<u64>,
+
+        /// Re-query and diff every table this often, even without notifications (0 disables)
+        #[arg(
+            long = "reconcile-interval",
+            value_name = "SECS",
+            default_value_t = 600
+        )]
+        reconcile_interval_secs: u64,
+
+        /// Time a sync may spend before deferring remaining tables to the next notification (0 disables)
+        #[arg(long = "cycle-budget", value_name = "SECS", default_value_t = 60)]
+        cycle_budget_secs: u64,
+
+        /// Fetch tournament participants in pages of this size, for services that paginate (0 fetches all at once)
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        participants_page_size: usize,
+
+        /// Number of recent matches remembered by `blobHash`, so seeing one again costs no Supabase call
+        #[arg(long, value_name = "N", default_value_t = 64)]
+        match_dedup_window: usize,
+
+        /// Only sync tournaments starting at or after this time, in microseconds since the Unix epoch
+        #[arg(long, value_name = "MICROS")]
+        tournaments_since: Option<u64>,
+
+        /// Let the service filter `--tournaments-since`; needs `allTournaments(since:)` support
+        #[arg(long, requires = "tournaments_since")]
+        tournaments_since_server_side: bool,
+
+        /// Delete all tournaments from Supabase once the service reports none on two consecutive syncs
+        #[arg(long, conflicts_with = "tournaments_since")]
+        prune_tournaments: bool,
+
+        /// Number of tournaments, with their participants and brackets, synced at the same time
+        #[arg(long, value_name = "N", default_value_t = 4)]
+        tournament_concurrency: usize,
+
+        /// Leave out tournaments with a prize pool but no prize type, or an unknown prize type, instead of writing them with a warning
+        #[arg(long)]
+        skip_inconsistent_prizes: bool,
+
+        /// Stop and exit with an error on the first failed write, instead of logging it and carrying on
+        #[arg(long)]
+        fail_fast: bool,
+
+        /// Where changes go; `stdout` prints them as JSON and needs no Supabase configuration
+        #[arg(long, value_enum, default_value_t)]
+        output: Output,
+    },
+    /// Submit an operation to an application, as a GraphQL mutation or raw bytes
+    Execute {
+        /// Application ID to submit the operation to
+        #[arg(long, value_name = "APP_ID", value_parser = parse_application_id)]
+        app_id: ApplicationId,
+
+        /// GraphQL mutation run against the application's service, e.g. `mutation { register }`
+        #[arg(long, value_name = "GRAPHQL", required_unless_present = "operation")]
+        mutation: Option<String>,
+
+        /// Hex-encoded operation, serialized with the application's ABI
+        #[arg(long, value_name = "HEX", conflicts_with = "mutation")]
+        operation: Option<String>,
+    },
+    /// Transfer tokens from the chain balance to another chain
+    Transfer {
+        /// Recipient chain
+        #[arg(long, value_name = "CHAIN_ID", value_parser = parse_chain_id)]
+        to: ChainId,
+
+        /// Number of tokens to send
+        #[arg(long, value_name = "TOKENS")]
+        amount: u64,
+
+        /// Wait until the recipient chain has received the transfer (needs it in the wallet)
+        #[arg(long)]
+        wait_receipt: bool,
+
+        /// How long `--wait-receipt` waits before giving up
+        #[arg(long = "receipt-timeout", value_name = "SECS", default_value_t = 60)]
+        receipt_timeout_secs: u64,
+    },
+    /// Synchronize the chain and process its inbox now, without waiting for a notification
+    ///
+    /// Works on the wallet's default chain, or on the one selected with the global `--chain-id`.
+    CatchUp,
+    /// Manage the keys owning the default chain
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommand,
     },
     /// Subscribe and watch an existing application
     ChainService {
         /// Application ID to subscribe to
-        #[arg(long, value_name = "APP_ID")]
-        app_id: String,
+        #[arg(long, value_name = "APP_ID", value_parser = parse_application_id)]
+        app_id: ApplicationId,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// Add a new key to the keystore and to the owners of the default chain
+    Rotate {
+        /// Weight of the new key among the chain owners
+        #[arg(long, default_value_t = 100)]
+        weight: u64,
+
+        /// Sign for the default chain with the new key from the next start
+        #[arg(long)]
+        make_default: bool,
+
+        /// Remove the previous key from the chain owners right away
+        #[arg(long, requires = "make_default")]
+        revoke_previous: bool,
+    },
+    /// Remove a key from the owners of the default chain, e.g. the previous key of a rotation
+    Revoke {
+        /// Owner to remove
+        #[arg(long, value_name = "OWNER", value_parser = parse_owner)]
+        owner: AccountOwner,
     },
 }
 
+impl Commands {
+    /// Whether the command runs long enough to need the background chain listener
+    fn needs_background_sync(&self) -> bool {
+        matches!(self, Commands::Watch { .. } | Commands::ChainService { .. })
+    }
+}
+
+/// Parses a number between 0 and 1, such as `--backoff-jitter`.
+fn parse_fraction(raw: &str) -> Result<f64> {
+    let fraction: f64 = raw.parse()?;
+    if !(0.0..=1.0).contains(&fraction) {
+        anyhow::bail!("{} is not between 0 and 1", fraction);
+    }
+    Ok(fraction)
+}
+
+/// Parses `--app-id` at argument-parse time, so a malformed id is rejected up front.
+fn parse_application_id(raw: &str) -> Result<ApplicationId> {
+    ApplicationId::from_str(raw.trim()).with_context(|| format!("invalid application id {:?}", raw))
+}
+
+/// Parses `--owner` at argument-parse time.
+fn parse_owner(raw: &str) -> Result<AccountOwner> {
+    AccountOwner::from_str(raw.trim()).with_context(|| format!("invalid owner {:?}", raw))
+}
+
+/// Returns the initialization argument given inline or read from `file`,
+/// after checking that it is valid JSON.
+fn load_json_argument(inline: Option<String>, file: Option<&Path>) -> Result<Option<String>> {
+    let argument = match file {
+        Some(file) => Some(
+            std::fs::read_to_string(file)
+                .with_context(|| format!("Failed to read {}", file.display()))?,
+        ),
+        None => inline,
+    };
+    if let Some(ref argument) = argument {
+        serde_json::from_str::<serde_json::Value>(argument)
+            .context("Initialization argument is not valid JSON")?;
+    }
+    Ok(argument)
+}
+
 /// Validates that the wallet directory contains all required files
 fn validate_wallet_directory(wallet_path: &Path) -> Result<()> {
     // Check if the directory exists
@@ -127,7 +443,8 @@ fn validate_wallet_directory(wallet_path: &Path) -> Result<()> {
     }
 
     println!(
-        "✓ Wallet directory validation successful: {}",
+        "{} Wallet directory validation successful: {}",
+        mark_ok(),
         wallet_path.display()
     );
     println!("  - wallet.json: found");
@@ -137,327 +454,363 @@ fn validate_wallet_directory(wallet_path: &Path) -> Result<()> {
     Ok(())
 }
 
-// Cache struct
-#[derive(Clone, Debug)]
-struct CachedState {
-    count: Option<u64>,
-    leaderboard: Option<Vec<Leaderboard>>,
-    matches: Option<MatchHistory>,
-    tournaments: HashMap<String, Tournament>,
-    participants: HashMap<String, HashMap<String, TournamentParticipant>>,
-}
-
-fn init_logging() {
-    tracing_subscriber::Registry::default()
-        .with(fmt::layer().with_target(true).without_time()) // show targets, optional timestamps
-        .with(EnvFilter::from_default_env()) // reads RUST_LOG
-        .init();
-}
-
-const SUB_QUERY: &str = r#"{ "query": "mutation { subscribe }" }"#;
-
 #[tokio::main]
 async fn main() -> Result<()> {
-    init_logging();
-
     let args = Args::parse();
 
+    if let Commands::Version { verbose } = args.command {
+        build_info::print(verbose);
+        return Ok(());
+    }
+
+    let instance_id = args
+        .instance_id
+        .clone()
+        .or_else(sysinfo::System::host_name)
+        .unwrap_or_else(|| "unknown".to_string());
+    // Flushes `--log-file` when dropped at the end of `main`.
+    let _log_guard = init_logging(
+        &instance_id,
+        args.quiet,
+        args.color,
+        args.no_emoji,
+        &args.log_file,
+    )?;
+    logging::set_max_body_log_bytes(args.max_body_log_bytes);
+    limits::set_max_response_bytes(args.max_response_bytes);
+    util::set_backoff_jitter(args.backoff_jitter);
+    metrics::set_instance_id(&instance_id);
+
+    // Runs before the wallet is loaded, since that is part of what it checks.
+    if let Commands::Doctor { app_id } = args.command {
+        return doctor::run(DoctorArgs {
+            keystore_path: args.keystore_path,
+            storage: args.storage,
+            faucets: args.faucets,
+            supabase: args.supabase,
+            chain_id: args.chain_id,
+            app_id,
+        })
+        .await;
+    }
+
+    // Only talks to Supabase, so no wallet is needed.
+    if let Commands::EnsureSchema {
+        apply,
+        sql_function,
+    } = args.command
+    {
+        return schema::run(EnsureSchemaArgs {
+            supabase: args.supabase,
+            function: sql_function,
+            apply,
+        })
+        .await;
+    }
+
     // Validate wallet directory if provided
     if let Some(ref wallet_path) = args.wallet_path {
         validate_wallet_directory(wallet_path).context("Wallet directory validation failed")?;
     }
 
     // Initialize the persistent wallet
-    let persistent_wallet = PersistentWallet::new(args.keystore_path).await?;
-    let client_context = Client::new(&persistent_wallet, None).await?;
+    let mut persistent_wallet =
+        PersistentWallet::new(args.keystore_path, &args.storage, &args.faucets).await?;
+    let client_context = Client::new(
+        &persistent_wallet,
+        Some(args.listener.options()),
+        args.command.needs_background_sync(),
+    )
+    .await?;
+
+    let chain = client_context.chain(args.chain_id).await?;
+    let mut query_source: Arc<dyn QuerySource> = match (&args.record, &args.replay) {
+        (Some(dir), _) => Arc::new(Recorder::new(dir)?),
+        (_, Some(dir)) => Arc::new(Replayer::load(dir)?),
+        _ => Arc::new(Network),
+    };
+    if args.trace_queries {
+        query_source = Arc::new(Traced::new(query_source));
+    }
 
-    let chain = client_context.chain(None).await?;
+    if args.metrics && !matches!(args.command, Commands::Metrics) {
+        start_resource_logger();
+    }
+
+    let shutdown_token = CancellationToken::new();
+    let subscription = SubscriptionOptions {
+        idle_timeout: (args.idle_timeout_secs > 0)
+            .then(|| Duration::from_secs(args.idle_timeout_secs)),
+        max_reconnect_attempts: (args.reconnect_max_attempts > 0)
+            .then_some(args.reconnect_max_attempts),
+        queue_depth: args.notification_queue_depth,
+        cancel: shutdown_token.clone(),
+        ..SubscriptionOptions::default()
+    };
+    // What the shutdown sequence has to wind down, set by long-running commands
+    let mut notifications = None;
+    let mut running_watcher = None;
+
+    let max_lag = (args.max_notification_lag_secs > 0)
+        .then(|| Duration::from_secs(args.max_notification_lag_secs));
+    let mut lag_guard = match (args.command.needs_background_sync(), max_lag) {
+        (true, Some(max_lag)) => Some(health::spawn_lag_guard(max_lag)),
+        _ => None,
+    };
+    // Created up front so `/health` can list the chains `chain-service` runs.
+    let client_manager = ChainClientManager::default();
+    if let Some(addr) = args.http_addr {
+        let chains =
+            matches!(args.command, Commands::ChainService { .. }).then(|| client_manager.clone());
+        server::spawn(addr, max_lag, chains).await?;
+    }
 
     // Handle commands
     match args.command {
         Commands::Metrics => {
             start_resource_logger();
         }
+        Commands::Doctor { .. } => unreachable!("doctor runs before the wallet is loaded"),
+        Commands::EnsureSchema { .. } => {
+            unreachable!("ensure-schema runs before the wallet is loaded")
+        }
+        Commands::Version { .. } => unreachable!("version is printed before anything starts"),
         Commands::Deploy {
             path,
             json_argument,
+            json_argument_file,
         } => {
-            println!("🚀 Deploying application...");
+            let json_argument = load_json_argument(json_argument, json_argument_file.as_deref())?;
+
+            println!("{}Deploying application...", logging::emoji("🚀 "));
             println!("  - Project path: {}", path.display());
 
             if let Some(ref json_arg) = json_argument {
                 println!("  - JSON argument: {}", json_arg);
             }
 
-            println!("✓ Deployment complete");
+            println!("{} Deployment complete", mark_ok());
         }
 
-        Commands::Watch { app_id } => {
+        Commands::Watch {
+            app_id,
+            flush_interval_ms,
+            flush_threshold,
+            tables,
+            from_block,
+            reconcile_interval_secs,
+            cycle_budget_secs,
+            output,
+            participants_page_size,
+            match_dedup_window,
+            tournaments_since,
+            tournaments_since_server_side,
+            prune_tournaments,
+            tournament_concurrency,
+            skip_inconsistent_prizes,
+            fail_fast,
+        } => {
             println!(" Watch mode enabled");
             println!(" - Application ID: {}", app_id);
+            let write_policy = if fail_fast {
+                WritePolicy::FailFast
+            } else {
+                WritePolicy::KeepGoing
+            };
+            info!("On write errors: {}", write_policy);
+
+            let app = chain
+                .application(app_id)
+                .with_source(Arc::clone(&query_source));
+            app.ping().await?;
+
+            app.query(&graphql::subscribe().to_json()).await?;
+
+            // Only required when changes are written to Supabase
+            let supabase_client = if output.to_supabase() {
+                Some(Arc::new(SupabaseClient::with_config(
+                    args.supabase.clone(),
+                )?))
+            } else {
+                None
+            };
+            let config = WatchConfig {
+                tables,
+                strict_schema: args.strict_schema,
+                flush_interval: flush_interval_ms.map(Duration::from_millis),
+                flush_threshold,
+                from_block: from_block.map(BlockHeight),
+                cycle_budget: (cycle_budget_secs > 0)
+                    .then(|| Duration::from_secs(cycle_budget_secs)),
+                output,
+                participants_page_size: (participants_page_size > 0)
+                    .then_some(participants_page_size),
+                match_dedup_window,
+                tournaments_since,
+                tournaments_since_server_side,
+                prune_tournaments,
+                tournament_concurrency,
+                skip_inconsistent_prizes,
+                write_policy,
+                shutdown: shutdown_token.clone(),
+            };
+            let watcher = Watcher::new(app, supabase_client, config);
+            watcher.react_to_events(client_context.subscribe_events());
+            if reconcile_interval_secs > 0 {
+                watcher.spawn_reconciler(Duration::from_secs(reconcile_interval_secs));
+            }
 
-            let app = chain.application(&app_id).await?;
-
-            app.query(SUB_QUERY).await?;
-
-            // Create shared cache
-            let cache = Arc::new(Mutex::new(CachedState {
-                count: None,
-                leaderboard: None,
-                matches: None,
-                tournaments: HashMap::new(),
-                participants: HashMap::new(),
-            }));
-
-            let app_arc = Arc::new(app);
-            let supabase_client = Arc::new(SupabaseClient::new()?);
-            let cache_clone = Arc::clone(&cache);
-
-            chain.on_notification(move || {
-                let app = Arc::clone(&app_arc);
-                let cache = Arc::clone(&cache_clone);
-                let supabase_client = Arc::clone(&supabase_client);
-
-                async move {
-                    let response_t = match app.query(QUERY_TOURNAMENTS).await {
-                        Ok(r) => r,
-                        Err(e) => {
-                            eprintln!("✗ Leaderboard query failed: {}", e);
-                            return;
-                        }
-                    };
-
-                    let tournaments_resp: TournamentResponse =
-                        match serde_json::from_str(&response_t) {
-                            Ok(d) => d,
-                            Err(e) => {
-                                eprintln!("✗ Failed to parse tournaments: {:?}", e);
-                                return;
-                            }
-                        };
-                    println!("tournament: {:?}", tournaments_resp);
-
-                    let mut cache_guard = cache.lock().await;
-
-                    for tournament in tournaments_resp.data.all_tournaments {
-                        // Check if tournament changed
-                        let should_update = match cache_guard.tournaments.get(&tournament.tournament_id) {
-                            Some(cached_t) => cached_t != &tournament,
-                            None => true,
-                        };
-
-                        if should_update {
-                             println!("Tournament {} changed or new, updating Supabase...", tournament.tournament_id);
-                             // Use insert which maps to upsert for TournamentDB to avoid full delete/insert cycle
-                             match tournament.for_db().insert(&supabase_client).await {
-                                Ok(_) => {
-                                    println!("✓ Updated tournament {} in Supabase", tournament.tournament_name);
-                                    cache_guard.tournaments.insert(tournament.tournament_id.clone(), tournament.clone());
-                                }
-                                Err(e) => eprintln!("✗ Failed to update tournament: {}", e),
-                            }
-                        }
-
-                        let query = participants_query(&tournament.tournament_id);
-                        let response_p = match app.query(&query).await {
-                            Ok(r) => r,
-                            Err(e) => {
-                                eprintln!("✗ Participants query failed: {}", e);
-                                return;
-                            }
-                        };
-
-                        let participants_resp: ParticipantResponse =
-                            match serde_json::from_str(&response_p) {
-                                Ok(d) => d,
-                                Err(e) => {
-                                    eprintln!("✗ Failed to parse participants: {}", e);
-                                    return;
-                                }
-                            };
-
-                        let current_participants_map: HashMap<String, TournamentParticipant> = participants_resp
-                            .data
-                            .participants
-                            .into_iter()
-                            .map(|p| (p.id.clone(), p))
-                            .collect();
-
-                        let tournament_participants_cache = cache_guard.participants.entry(tournament.tournament_id.clone()).or_default();
-
-                        for (p_id, participant) in &current_participants_map {
-                            let p_should_update = match tournament_participants_cache.get(p_id) {
-                                Some(cached_p) => cached_p != participant,
-                                None => true,
-                            };
-
-                            if p_should_update {
-                                println!("Participant {} changed or new, updating Supabase...", p_id);
-                                match participant
-                                    .for_db(tournament.tournament_id.clone())
-                                    .insert(&supabase_client)
-                                    .await
-                                {
-                                    Ok(_) => {
-                                        println!("✓ Updated participant {} in Supabase", p_id);
-                                        // Update the specific participant in the cache
-                                        tournament_participants_cache.insert(p_id.clone(), participant.clone());
-                                    }
-                                    Err(e) => eprintln!("✗ Failed to update participant: {}", e),
-                                }
-                            }
-                        }
-                    }
-                    // Leaderboard
-                    let query_leaderboard = r#"{ "query": "query { leaderboard { elo id name matches won lost } }" }"#;
-                    let response_l = match app.query(query_leaderboard).await {
-                         Ok(r) => r,
-                         Err(e) => {
-                             eprintln!("✗ Leaderboard query failed: {}", e);
-                             return;
-                         }
-                    };
-
-                    let query_count = r#"{ "query": "query { count }" }"#;
-                    let response_c = match app.query(query_count).await {
-                        Ok(r) => r,
-                        Err(e) => {
-                            eprintln!("✗ Count query failed: {}", e);
-                            return;
-                        }
-                    };
-
-                    let query_matches = r#"{ "query": "query { matchHistoryLast { you { id name } opponent { id name } blobHash } }" }"#;
-                    let response_m = match app.query(query_matches).await {
-                        Ok(r) => r,
-                        Err(e) => {
-                            eprintln!("✗ Matches query failed: {}", e);
-                            return;
-                        }
-                    };
-
-                    // Parse responses
-                     let leaderboard_data: LeaderBoardResponse =
-                        match serde_json::from_str(&response_l) {
-                            Ok(d) => d,
-                            Err(e) => {
-                                eprintln!("✗ Failed to parse leaderboard: {}", e);
-                                return;
-                            }
-                        };
-
-                    let count_data: CountResponse = match serde_json::from_str(&response_c) {
-                        Ok(d) => d,
-                        Err(e) => {
-                            eprintln!("✗ Failed to parse count: {}", e);
-                            return;
-                        }
-                    };
-
-                    let matches_data: Option<MatchHistoryResponse> =
-                        match serde_json::from_str(&response_m) {
-                            Ok(d) => Some(d),
-                            Err(e) => {
-                                eprintln!("✗ Failed to parse match history: {}", e);
-                                None
-                            }
-                        };
-
-                    let new_leaderboard = leaderboard_data.data.leaderboard;
-                    let new_count = count_data.data.count;
-
-                    // Update count if changed
-                    if cache_guard.count != Some(new_count) {
-                        println!("📊 Count changed: {:?} -> {}", cache_guard.count, new_count);
-
-                        let count_record = GameCount {
-                            id: "singleton".to_string(),
-                            count: new_count.to_string(),
-                        };
-
-                        match count_record.insert(&supabase_client).await {
-                            Ok(_) => {
-                                println!("✓ Updated count in Supabase");
-                                cache_guard.count = Some(new_count);
-                            }
-                            Err(e) => eprintln!("✗ Failed to update count: {}", e),
-                        }
-                    }
-
-                    // Update leaderboard if changed
-                    if cache_guard.leaderboard.as_ref() != Some(&new_leaderboard) {
-                        println!(
-                            "Leaderboard changed, updating {} entries",
-                            new_leaderboard.len()
-                        );
-
-                        match Leaderboard::replace_all(new_leaderboard.clone(), &supabase_client)
-                            .await
+            running_watcher = Some(Arc::clone(&watcher));
+            notifications = Some(chain.on_notification_with_details(
+                subscription,
+                move |notification| {
+                    let watcher = Arc::clone(&watcher);
+                    async move {
+                        // A new round doesn't change application state, nothing to sync.
+                        if let Some(Notification {
+                            reason: Reason::NewRound { .. },
+                            ..
+                        }) = notification
                         {
-                            Ok(_) => {
-                                println!("✓ Updated leaderboard in Supabase");
-                                cache_guard.leaderboard = Some(new_leaderboard);
-                            }
-                            Err(e) => eprintln!("✗ Failed to update leaderboard: {}", e),
-                        }
-                    }
-
-                    if let Some(match_history) = matches_data {
-                         if let Some(new_match) = match_history.data.match_history_last {
-                            // Update Match history if changed
-                            if cache_guard.matches.as_ref() != Some(&new_match) {
-                                println!("Last match update: {:?}", new_match);
-
-                                match MatchHistoryDB::insert(&new_match.for_db(), &supabase_client)
-                                    .await
-                                {
-                                    Ok(_) => {
-                                        println!("✓ Updated matches list in Supabase");
-                                        cache_guard.matches = Some(new_match);
-                                    }
-                                    Err(e) => eprintln!("✗ Failed to update matches list: {}", e),
-                                }
-                            }
+                            return;
                         }
+                        health::record_notification();
+                        watcher.sync().await
                     }
-                }
-            });
+                },
+            ));
 
             println!(" Watching for events...");
         }
+        Commands::Execute {
+            app_id,
+            mutation,
+            operation,
+        } => {
+            let app = chain
+                .application(app_id)
+                .with_source(Arc::clone(&query_source));
+
+            if let Some(mutation) = mutation {
+                let request = graphql::Request::raw(mutation).to_json();
+                let response = app.query(&request).await?;
+                println!("{} Mutation executed: {}", mark_ok(), response);
+            } else if let Some(operation) = operation {
+                let bytes = hex::decode(operation.trim_start_matches("0x"))
+                    .context("`--operation` must be hex-encoded")?;
+                let hash = app.execute_operation(bytes).await?;
+                println!("{} Operation included in block {}", mark_ok(), hash);
+            }
+            return Ok(());
+        }
+        Commands::Transfer {
+            to,
+            amount,
+            wait_receipt,
+            receipt_timeout_secs,
+        } => {
+            let receipt = chain
+                .transfer(TransferParams {
+                    donor: None,
+                    amount,
+                    recipient: Account::chain(to),
+                })
+                .await?;
+            println!(
+                "{} Transfer sent in block {} (height {})",
+                mark_ok(),
+                receipt.hash,
+                receipt.height
+            );
+
+            if wait_receipt {
+                chain
+                    .wait_for_receipt(
+                        to,
+                        receipt.height,
+                        Duration::from_secs(receipt_timeout_secs),
+                        Duration::from_secs(1),
+                    )
+                    .await?;
+                println!("{} Transfer received by {}", mark_ok(), to);
+            }
+            return Ok(());
+        }
+        Commands::CatchUp => {
+            chain.process_inbox().await?;
+            println!(
+                "{} Inbox of chain {} processed",
+                mark_ok(),
+                chain.chain_id()
+            );
+            return Ok(());
+        }
+        Commands::Keys { command } => {
+            match command {
+                KeysCommand::Rotate {
+                    weight,
+                    make_default,
+                    revoke_previous,
+                } => {
+                    keys::rotate(
+                        &chain,
+                        &mut persistent_wallet,
+                        RotateArgs {
+                            weight,
+                            make_default,
+                            revoke_previous,
+                        },
+                    )
+                    .await?
+                }
+                KeysCommand::Revoke { owner } => {
+                    keys::revoke(&chain, &persistent_wallet, owner).await?
+                }
+            }
+            return Ok(());
+        }
         Commands::ChainService { app_id } => {
-            let app = chain.application(&app_id.clone()).await?;
+            let app = chain
+                .application(app_id)
+                .with_source(Arc::clone(&query_source));
+            app.ping().await?;
 
-            app.query(SUB_QUERY).await?;
+            app.query(&graphql::subscribe().to_json()).await?;
             let app_arc = Arc::new(app);
 
-            let client_manager = ChainClientManager::default();
             let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+            let strict_schema = args.strict_schema;
 
-            chain.on_notification(move || {
-                let chains = r#"{ "query": "query { tournamentChains }" }"#;
+            notifications = Some(chain.on_notification_with(subscription, move || {
+                let chains = graphql::tournament_chains().to_json();
                 let app = Arc::clone(&app_arc);
                 let tx = tx.clone();
 
                 async move {
-                    let chain_response = match app.query(chains).await {
+                    health::record_notification();
+                    let chain_response = match app.query(&chains).await {
                         Ok(r) => r,
                         Err(e) => {
-                            eprintln!("✗ Chain query failed: {}", e);
+                            tracing::error!("Chain query failed: {}", e);
                             return;
                         }
                     };
 
                     let chains: Option<TournamentChainsResponse> =
-                        match serde_json::from_str(&chain_response) {
+                        match parse_response(&chain_response, strict_schema) {
                             Ok(d) => Some(d),
                             Err(e) => {
-                                eprintln!("✗ Failed to parse tournament chains: {}", e);
+                                tracing::error!("Failed to parse tournament chains: {}", e);
                                 None
                             }
                         };
 
                     if let Some(chains) = chains {
+                        health::record_success();
+                        tracing::debug!(
+                            chains = ?chains.data.tournament_chains,
+                            "Tournament chains reported by the application"
+                        );
                         if chains.data.tournament_chains.len() > 0 {
                             tx.send(chains.data.tournament_chains)
                                 .await
@@ -465,23 +818,94 @@ async fn main() -> Result<()> {
                         }
                     }
                 }
-            });
+            }));
 
             tokio::spawn(async move {
                 while let Some(chains) = rx.recv().await {
                     for id in chains {
-                        client_manager
-                            .ensure_running(id, &chain.client, &app_id)
-                            .await;
+                        if let Err(e) = client_manager
+                            .ensure_running(&id, &chain.client, app_id)
+                            .await
+                        {
+                            tracing::warn!(chain_id = %id.trim(), "Skipping tournament chain: {:#}", e);
+                        }
                     }
                 }
             });
             println!("Watching for tournament Chains...");
         }
     }
-    loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(300)).await;
+    // The notification task only ends on its own when it gave up resubscribing.
+    let (gave_up, lagging) = {
+        let notifications_ended = async {
+            match notifications.as_mut() {
+                Some(task) => {
+                    let _ = task.await;
+                }
+                None => std::future::pending().await,
+            }
+        };
+        let watcher_halted = async {
+            match &running_watcher {
+                Some(watcher) => watcher.halted().await,
+                None => std::future::pending().await,
+            }
+        };
+        let lag_exceeded = async {
+            match lag_guard.as_mut() {
+                Some(guard) => {
+                    let _ = guard.await;
+                }
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            () = shutdown::wait_for_signal() => (false, false),
+            () = notifications_ended => (true, false),
+            () = watcher_halted => (false, false),
+            () = lag_exceeded => (false, true),
+        }
+    };
+    if gave_up {
+        notifications = None;
+    }
+
+    // Wind down from the outside in, so nothing writes to storage that is
+    // already closing: notifications, in-flight writes, the chain listener,
+    // then storage itself.
+    shutdown_token.cancel();
+    shutdown::phase("stopping notifications", async {
+        if let Some(task) = notifications {
+            let _ = task.await;
+        }
+    })
+    .await;
+    shutdown::phase("draining writes", async {
+        if let Some(watcher) = &running_watcher {
+            watcher.drain().await;
+        }
+    })
+    .await;
+    shutdown::phase("stopping the chain listener", client_context.shutdown()).await;
+
+    info!("Shutdown: closing storage");
+    let halt_error = running_watcher
+        .as_ref()
+        .and_then(|watcher| watcher.take_halt_error());
+    drop(running_watcher);
+    drop(client_context);
+    drop(persistent_wallet);
+    // Tasks still holding the client are dropped with the runtime on return.
+    if let Some(e) = halt_error {
+        return Err(e.context("Stopped on a failed write (--fail-fast)"));
+    }
+    if gave_up {
+        anyhow::bail!("Lost the notification subscription, see --reconnect-max-attempts");
     }
+    if lagging {
+        anyhow::bail!("Stopped making progress, see --max-notification-lag");
+    }
+    Ok(())
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,209 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Value of the `instance` label, set once at startup from `--instance-id`
+static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+
+pub fn set_instance_id(instance_id: &str) {
+    let _ = INSTANCE_ID.set(instance_id.to_string());
+}
+
+fn instance_id() -> &'static str {
+    INSTANCE_ID.get().map(String::as_str).unwrap_or("unknown")
+}
+
+/// Upper bounds of the latency histogram buckets, in milliseconds
+const LATENCY_BUCKETS_MS: [u64; 8] = [10, 25, 50, 100, 250, 500, 1000, 5000];
+
+/// A Prometheus histogram of durations over [`LATENCY_BUCKETS_MS`]
+pub struct LatencyHistogram {
+    /// Observations per bucket, not cumulative; slower ones only count in `count`
+    buckets: [AtomicU64; LATENCY_BUCKETS_MS.len()],
+    count: AtomicU64,
+    sum_ms: AtomicU64,
+}
+
+impl LatencyHistogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; LATENCY_BUCKETS_MS.len()],
+            count: AtomicU64::new(0),
+            sum_ms: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, elapsed: Duration) {
+        let ms = elapsed.as_millis() as u64;
+        if let Some(i) = LATENCY_BUCKETS_MS.iter().position(|&bound| ms <= bound) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_ms.fetch_add(ms, Ordering::Relaxed);
+    }
+
+    /// Writes the cumulative `_bucket` lines; `_sum` and `_count` are
+    /// rendered with the other values.
+    fn render_buckets(&self, out: &mut String, name: &str) {
+        let mut cumulative = 0;
+        for (bound, bucket) in LATENCY_BUCKETS_MS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "{}_bucket{{instance=\"{}\",le=\"{}\"}} {}",
+                name,
+                instance_id(),
+                bound,
+                cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{instance=\"{}\",le=\"+Inf\"}} {}",
+            name,
+            instance_id(),
+            self.count.load(Ordering::Relaxed)
+        );
+    }
+}
+
+/// HTTP methods of Supabase requests: selects and counts, inserts, upserts
+/// and calls, updates, deletes
+const SUPABASE_METHODS: [&str; 4] = ["GET", "POST", "PATCH", "DELETE"];
+
+/// Supabase requests retried, per HTTP method, i.e. per kind of operation
+pub struct RetryCounters {
+    /// Retries per method of [`SUPABASE_METHODS`]; other methods only count in `total`
+    methods: [AtomicU64; SUPABASE_METHODS.len()],
+    total: AtomicU64,
+}
+
+impl RetryCounters {
+    const fn new() -> Self {
+        Self {
+            methods: [const { AtomicU64::new(0) }; SUPABASE_METHODS.len()],
+            total: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, method: &reqwest::Method) {
+        if let Some(i) = SUPABASE_METHODS
+            .iter()
+            .position(|known| *known == method.as_str())
+        {
+            self.methods[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Writes one line per method; the total is rendered with the other values.
+    fn render_methods(&self, out: &mut String, name: &str) {
+        for (method, count) in SUPABASE_METHODS.iter().zip(&self.methods) {
+            let _ = writeln!(
+                out,
+                "{}{{instance=\"{}\",method=\"{}\"}} {}",
+                name,
+                instance_id(),
+                method,
+                count.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+/// Process-wide counters and gauges, printed by the resource logger.
+pub struct Metrics {
+    /// 0 = closed, 1 = open, 2 = half-open
+    pub supabase_circuit_state: AtomicU64,
+    pub supabase_circuit_opened_total: AtomicU64,
+    pub supabase_short_circuited_total: AtomicU64,
+    /// Requests retried after a gateway error, see `--supabase-retries`
+    pub supabase_retries: RetryCounters,
+    /// Sync cycles that ran out of time and deferred their remaining work
+    pub sync_budget_exhausted_total: AtomicU64,
+    /// Notifications received but not yet handed to the callback
+    pub notification_queue_depth: AtomicU64,
+    /// Notifications dropped because the queue was full
+    pub notifications_dropped_total: AtomicU64,
+    /// Application query latencies, recorded with `--trace-queries`
+    pub query_latency: LatencyHistogram,
+}
+
+pub static METRICS: Metrics = Metrics {
+    supabase_circuit_state: AtomicU64::new(0),
+    supabase_circuit_opened_total: AtomicU64::new(0),
+    supabase_short_circuited_total: AtomicU64::new(0),
+    supabase_retries: RetryCounters::new(),
+    sync_budget_exhausted_total: AtomicU64::new(0),
+    notification_queue_depth: AtomicU64::new(0),
+    notifications_dropped_total: AtomicU64::new(0),
+    query_latency: LatencyHistogram::new(),
+};
+
+impl Metrics {
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, value) in self.values() {
+            let _ = writeln!(out, "{}{{instance=\"{}\"}} {}", name, instance_id(), value);
+        }
+        self.query_latency
+            .render_buckets(&mut out, "pws_query_latency_ms");
+        self.supabase_retries
+            .render_methods(&mut out, "pws_supabase_retries_by_method_total");
+        out
+    }
+
+    /// Renders all metrics as a single `name=value` line.
+    pub fn summary(&self) -> String {
+        let values = self
+            .values()
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!("instance={} {}", instance_id(), values)
+    }
+
+    fn values(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            (
+                "pws_supabase_circuit_state",
+                self.supabase_circuit_state.load(Ordering::Relaxed),
+            ),
+            (
+                "pws_supabase_circuit_opened_total",
+                self.supabase_circuit_opened_total.load(Ordering::Relaxed),
+            ),
+            (
+                "pws_supabase_short_circuited_total",
+                self.supabase_short_circuited_total.load(Ordering::Relaxed),
+            ),
+            (
+                "pws_supabase_retries_total",
+                self.supabase_retries.total.load(Ordering::Relaxed),
+            ),
+            (
+                "pws_sync_budget_exhausted_total",
+                self.sync_budget_exhausted_total.load(Ordering::Relaxed),
+            ),
+            (
+                "pws_notification_queue_depth",
+                self.notification_queue_depth.load(Ordering::Relaxed),
+            ),
+            (
+                "pws_notifications_dropped_total",
+                self.notifications_dropped_total.load(Ordering::Relaxed),
+            ),
+            (
+                "pws_query_latency_ms_sum",
+                self.query_latency.sum_ms.load(Ordering::Relaxed),
+            ),
+            (
+                "pws_query_latency_ms_count",
+                self.query_latency.count.load(Ordering::Relaxed),
+            ),
+        ]
+    }
+}
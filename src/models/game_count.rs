@@ -16,7 +16,8 @@ pub struct CountData {
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 pub struct GameCount {
     pub id: String,
-    pub count: String,
+    /// Stored as `bigint`, so it can be compared and aggregated in SQL
+    pub count: u64,
 }
 
 #[async_trait]
@@ -29,8 +30,19 @@ impl SupabaseModel for GameCount {
         "id"
     }
 
+    fn columns() -> &'static [&'static str] {
+        &["id", "count"]
+    }
+
+    fn schema() -> &'static str {
+        r#"create table if not exists "gameCount" (
+            id text primary key,
+            count bigint not null
+        )"#
+    }
+
     async fn insert(&self, client: &SupabaseClient) -> Result<()> {
-        client.upsert(self).await
+        self.upsert(client).await
     }
 
     async fn insert_many(_records: Vec<Self>, _client: &SupabaseClient) -> Result<()> {
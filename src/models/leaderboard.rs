@@ -1,7 +1,9 @@
+use crate::models::match_history::Player;
 use crate::supabase::{SupabaseClient, SupabaseModel};
 use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 
 #[derive(Debug, Deserialize)]
 pub struct LeaderboardData {
@@ -24,8 +26,75 @@ pub struct Leaderboard {
     pub lost: u32,
 }
 
+/// A leaderboard row as written to Supabase, with the derived win rate and rank
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardDB {
+    pub id: String,
+    pub name: Option<String>,
+    pub elo: u32,
+    pub matches: u32,
+    pub won: u32,
+    pub lost: u32,
+    /// `won / matches`, 0 for players without matches
+    pub win_rate: f64,
+    /// 1 for the top player, see [`Leaderboard::rank_cmp`]
+    pub rank: u32,
+}
+
+impl Leaderboard {
+    /// Ranking order: higher elo first, then more wins, then by id, so every
+    /// player gets a distinct rank whatever order the service lists them in.
+    pub fn rank_cmp(&self, other: &Self) -> Ordering {
+        other
+            .elo
+            .cmp(&self.elo)
+            .then_with(|| other.won.cmp(&self.won))
+            .then_with(|| self.id.cmp(&other.id))
+    }
+
+    /// The rows of a whole leaderboard, ranked by [`Leaderboard::rank_cmp`].
+    pub fn ranked_for_db(entries: &[Leaderboard]) -> Vec<LeaderboardDB> {
+        let mut sorted: Vec<&Leaderboard> = entries.iter().collect();
+        sorted.sort_by(|a, b| a.rank_cmp(b));
+        sorted
+            .into_iter()
+            .zip(1..)
+            .map(|(entry, rank)| entry.for_db(rank))
+            .collect()
+    }
+
+    pub fn for_db(&self, rank: u32) -> LeaderboardDB {
+        LeaderboardDB {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            elo: self.elo,
+            matches: self.matches,
+            won: self.won,
+            lost: self.lost,
+            win_rate: win_rate(self.won, self.matches),
+            rank,
+        }
+    }
+}
+
+/// Fraction of `matches` that were won, without dividing by zero when no
+/// match was played.
+pub fn win_rate(won: u32, matches: u32) -> f64 {
+    f64::from(won) / f64::from(matches.max(1))
+}
+
+impl From<&Leaderboard> for Player {
+    fn from(entry: &Leaderboard) -> Self {
+        Player {
+            id: entry.id.clone(),
+            name: entry.name.clone(),
+        }
+    }
+}
+
 #[async_trait]
-impl SupabaseModel for Leaderboard {
+impl SupabaseModel for LeaderboardDB {
     fn table_name() -> &'static str {
         "leaderboard"
     }
@@ -34,12 +103,23 @@ impl SupabaseModel for Leaderboard {
         "id"
     }
 
-    async fn insert(&self, client: &SupabaseClient) -> Result<()> {
-        client.insert(self).await
+    fn columns() -> &'static [&'static str] {
+        &[
+            "id", "name", "elo", "matches", "won", "lost", "winRate", "rank",
+        ]
     }
 
-    async fn insert_many(records: Vec<Self>, client: &SupabaseClient) -> Result<()> {
-        client.insert_many(&records).await
+    fn schema() -> &'static str {
+        r#"create table if not exists leaderboard (
+            id text primary key,
+            name text,
+            elo integer not null,
+            matches integer not null,
+            won integer not null,
+            lost integer not null,
+            "winRate" double precision not null,
+            rank integer not null
+        )"#
     }
 
     async fn replace(&self, _client: &SupabaseClient) -> Result<()> {
@@ -54,3 +134,77 @@ impl SupabaseModel for Leaderboard {
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, elo: u32, won: u32, lost: u32) -> Leaderboard {
+        Leaderboard {
+            id: id.to_string(),
+            name: None,
+            elo,
+            matches: won + lost,
+            won,
+            lost,
+        }
+    }
+
+    /// Ids of the ranked rows, checking ranks run from 1 without gaps
+    fn ranked_ids(entries: &[Leaderboard]) -> Vec<String> {
+        let rows = Leaderboard::ranked_for_db(entries);
+        let ranks: Vec<u32> = rows.iter().map(|row| row.rank).collect();
+        assert_eq!(ranks, (1..=rows.len() as u32).collect::<Vec<_>>());
+        rows.into_iter().map(|row| row.id).collect()
+    }
+
+    #[test]
+    fn higher_elo_ranks_first() {
+        let entries = [entry("a", 1200, 5, 5), entry("b", 1400, 1, 9)];
+        assert_eq!(ranked_ids(&entries), ["b", "a"]);
+    }
+
+    #[test]
+    fn equal_elo_is_broken_by_wins_then_id() {
+        let entries = [
+            entry("c", 1200, 3, 0),
+            entry("b", 1200, 5, 0),
+            entry("a", 1200, 3, 2),
+        ];
+        assert_eq!(ranked_ids(&entries), ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn ranks_do_not_depend_on_input_order() {
+        let entries = vec![
+            entry("a", 1200, 3, 0),
+            entry("b", 1200, 3, 0),
+            entry("c", 1300, 0, 3),
+            entry("d", 1200, 4, 0),
+        ];
+        let expected = ranked_ids(&entries);
+        let mut reversed = entries.clone();
+        reversed.reverse();
+        assert_eq!(ranked_ids(&reversed), expected);
+        let mut rotated = entries;
+        rotated.rotate_left(1);
+        assert_eq!(ranked_ids(&rotated), expected);
+    }
+
+    #[test]
+    fn win_rate_without_matches_is_zero() {
+        assert_eq!(win_rate(0, 0), 0.0);
+        assert_eq!(entry("a", 1200, 0, 0).for_db(1).win_rate, 0.0);
+    }
+
+    #[test]
+    fn win_rate_of_all_wins_is_one() {
+        assert_eq!(win_rate(7, 7), 1.0);
+        assert_eq!(entry("a", 1200, 7, 0).for_db(1).win_rate, 1.0);
+    }
+
+    #[test]
+    fn win_rate_is_the_share_of_matches_won() {
+        assert_eq!(entry("a", 1200, 1, 3).for_db(1).win_rate, 0.25);
+    }
+}
@@ -3,6 +3,8 @@ use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
+/// A player as identified across tables: match history, participants and the leaderboard
+/// all convert into this type so their rows can be joined on `id`.
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 pub struct Player {
     pub id: String,
@@ -44,7 +46,23 @@ pub struct MatchHistoryDB {
     pub blob_hash: String,
 }
 
+impl Player {
+    /// Whether `other` refers to the same player, regardless of which table it came from.
+    pub fn is_same<P: Into<Player>>(&self, other: P) -> bool {
+        self.id == other.into().id
+    }
+}
+
 impl MatchHistory {
+    pub fn players(&self) -> [&Player; 2] {
+        [&self.you, &self.opponent]
+    }
+
+    /// Whether the given player took part in this match.
+    pub fn involves(&self, player_id: &str) -> bool {
+        self.you.id == player_id || self.opponent.id == player_id
+    }
+
     pub fn for_db(&self) -> MatchHistoryDB {
         let data = self.clone();
         MatchHistoryDB {
@@ -64,11 +82,32 @@ impl SupabaseModel for MatchHistoryDB {
     }
 
     fn primary_key() -> &'static str {
-        "id"
+        "blobHash"
+    }
+
+    fn columns() -> &'static [&'static str] {
+        &[
+            "player1Id",
+            "player1Name",
+            "player2Id",
+            "player2Name",
+            "blobHash",
+        ]
+    }
+
+    fn schema() -> &'static str {
+        r#"create table if not exists "matchHistory" (
+            "blobHash" text primary key,
+            "player1Id" text not null,
+            "player1Name" text,
+            "player2Id" text not null,
+            "player2Name" text
+        )"#
     }
 
+    /// Append-only: a match already recorded under the same `blobHash` is left as is.
     async fn insert(&self, client: &SupabaseClient) -> Result<()> {
-        client.insert(self).await
+        client.insert_ignore_conflicts(self).await
     }
 
     async fn insert_many(_records: Vec<Self>, _client: &SupabaseClient) -> Result<()> {
@@ -6,5 +6,74 @@ pub mod tournament;
 
 // Re-exports for cleaner imports
 pub use game_count::{CountData, CountResponse, GameCount};
-pub use leaderboard::{LeaderBoardResponse, Leaderboard, LeaderboardData};
-pub use match_history::{MatchHistory, MatchHistoryDB, MatchHistoryResponse};
+pub use leaderboard::{LeaderBoardResponse, Leaderboard, LeaderboardDB, LeaderboardData};
+pub use match_history::{MatchHistory, MatchHistoryDB, MatchHistoryResponse, Player};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::participants::ParticipantsDB;
+    use crate::models::tournament::{PlayerInfo, Tournament, TournamentParticipant};
+    use crate::supabase::SupabaseModel;
+
+    /// Fails unless `record` serializes to exactly the columns its model lists
+    fn assert_columns<T: SupabaseModel>(record: &T) {
+        let value = serde_json::to_value(record).unwrap();
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        let mut columns = T::columns().to_vec();
+        columns.sort_unstable();
+        assert_eq!(keys, columns, "columns of `{}`", T::table_name());
+    }
+
+    fn player(id: &str) -> Player {
+        Player {
+            id: id.to_string(),
+            name: Some(id.to_string()),
+        }
+    }
+
+    #[test]
+    fn columns_match_serialized_fields() {
+        assert_columns(&Tournament::sample("t1").for_db());
+        let participant = TournamentParticipant {
+            id: "p1".to_string(),
+            player: PlayerInfo {
+                name: Some("p1".to_string()),
+                elo: 1200,
+                matches: 3,
+                ath: 1250,
+            },
+        };
+        assert_columns(&participant.for_db("t1".to_string()));
+        let entry = Leaderboard {
+            id: "p1".to_string(),
+            name: Some("p1".to_string()),
+            elo: 1200,
+            matches: 3,
+            won: 2,
+            lost: 1,
+        };
+        assert_columns(&entry.for_db(1));
+        let last_match = MatchHistory {
+            you: player("p1"),
+            opponent: player("p2"),
+            blob_hash: "hash".to_string(),
+        };
+        assert_columns(&last_match.for_db());
+        assert_columns(&GameCount {
+            id: "count".to_string(),
+            count: 7,
+        });
+        assert_columns(&ParticipantsDB {
+            tournament_id: "t1".to_string(),
+            format: "swiss".to_string(),
+            standings_json: serde_json::json!([]),
+        });
+    }
+}
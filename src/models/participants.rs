@@ -1,3 +1,6 @@
+use crate::supabase::{SupabaseClient, SupabaseModel};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine};
 use linera_base::identifiers::AccountOwner;
 use serde::{Deserialize, Serialize};
@@ -16,6 +19,47 @@ pub struct SingleElimPlayer {
     opponents: Vec<String>,
 }
 
+/// A player of a bracket, whatever the tournament format.
+pub trait TournamentParticipants: std::fmt::Debug {
+    fn player_id(&self) -> &AccountOwner;
+
+    fn score(&self) -> u8;
+
+    /// Ids of the players already faced, in pairing order
+    fn opponents(&self) -> &[String];
+
+    /// The player's row in the standings
+    fn standing(&self) -> Standing<'_> {
+        Standing {
+            player_id: self.player_id(),
+            score: self.score(),
+            opponents: self.opponents(),
+        }
+    }
+}
+
+/// Implements [`TournamentParticipants`] for player structs sharing the
+/// `player_id`, `score` and `opponents` fields.
+macro_rules! impl_tournament_participants {
+    ($($player:ty),*) => {$(
+        impl TournamentParticipants for $player {
+            fn player_id(&self) -> &AccountOwner {
+                &self.player_id
+            }
+
+            fn score(&self) -> u8 {
+                self.score
+            }
+
+            fn opponents(&self) -> &[String] {
+                &self.opponents
+            }
+        }
+    )*};
+}
+
+impl_tournament_participants!(SwissPlayer, SingleElimPlayer);
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct SwissParticipants {
     pub players: Vec<SwissPlayer>,
@@ -35,17 +79,209 @@ pub enum Participants {
 }
 
 impl Participants {
-    pub fn decode(encoded: String) -> Self {
+    /// Decodes the base64-encoded postcard blob returned by the application.
+    pub fn decode(encoded: &str) -> Result<Self> {
         let bytes = general_purpose::STANDARD
             .decode(encoded)
-            .expect("invalid base64 input");
+            .context("invalid base64 input")?;
+
+        postcard::from_bytes::<Participants>(&bytes).context("postcard deserialization failed")
+    }
+
+    /// The tournament format, as stored in the `format` column
+    pub fn format(&self) -> &'static str {
+        match self {
+            Participants::Swiss(_) => "swiss",
+            Participants::SingleElim(_) => "single_elim",
+        }
+    }
 
-        postcard::from_bytes::<Participants>(&bytes).expect("postcard deserialization failed")
+    /// Every player's standing, in on-chain order.
+    fn unsorted_standings(&self) -> Vec<Standing<'_>> {
+        match self {
+            Participants::Swiss(swiss) => swiss.players.iter().map(|p| p.standing()).collect(),
+            Participants::SingleElim(single_elim) => {
+                single_elim.players.iter().map(|p| p.standing()).collect()
+            }
+        }
+    }
+
+    /// Every player with their score, in on-chain order.
+    pub fn scores(&self) -> impl Iterator<Item = (AccountOwner, u8)> + '_ {
+        self.unsorted_standings()
+            .into_iter()
+            .map(|standing| (*standing.player_id, standing.score))
+    }
+
+    /// Players ordered by score, highest first; ties keep the on-chain order.
+    pub fn standings(&self) -> Vec<Standing<'_>> {
+        let mut standings = self.unsorted_standings();
+        standings.sort_by(|a, b| b.score.cmp(&a.score));
+        standings
+    }
+
+    pub fn for_db(&self, tournament_id: String) -> Result<ParticipantsDB> {
+        Ok(ParticipantsDB {
+            tournament_id,
+            format: self.format().to_string(),
+            standings_json: serde_json::to_value(self.standings())?,
+        })
     }
 }
 
-pub trait TournamentParticipants: std::fmt::Debug {}
+/// A player's row in the standings of a bracket
+#[derive(Debug, Serialize)]
+pub struct Standing<'a> {
+    pub player_id: &'a AccountOwner,
+    pub score: u8,
+    pub opponents: &'a [String],
+}
 
-impl TournamentParticipants for SwissPlayer {}
+#[derive(Debug, Deserialize)]
+pub struct BracketResponse {
+    pub data: BracketData,
+}
 
-impl TournamentParticipants for SingleElimPlayer {}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BracketData {
+    /// Base64-encoded postcard [`Participants`], absent for unknown tournaments
+    pub encoded_participants: Option<String>,
+}
+
+/// The standings of a tournament's bracket, one row per tournament
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParticipantsDB {
+    pub tournament_id: String,
+    /// `swiss` or `single_elim`
+    pub format: String,
+    /// Array of `{ player_id, score, opponents }`, highest score first
+    pub standings_json: serde_json::Value,
+}
+
+#[async_trait]
+impl SupabaseModel for ParticipantsDB {
+    fn table_name() -> &'static str {
+        "tournament_brackets"
+    }
+
+    fn primary_key() -> &'static str {
+        "tournament_id"
+    }
+
+    fn columns() -> &'static [&'static str] {
+        &["tournament_id", "format", "standings_json"]
+    }
+
+    fn schema() -> &'static str {
+        r#"create table if not exists tournament_brackets (
+            tournament_id text primary key,
+            format text not null,
+            standings_json jsonb not null
+        )"#
+    }
+
+    async fn insert(&self, client: &SupabaseClient) -> Result<()> {
+        self.upsert(client).await
+    }
+
+    async fn insert_many(records: Vec<Self>, client: &SupabaseClient) -> Result<()> {
+        client.upsert_many(&records).await
+    }
+
+    async fn replace(&self, client: &SupabaseClient) -> Result<()> {
+        client
+            .delete_one::<Self>(&self.tournament_id)
+            .await?
+            .insert(self)
+            .await
+    }
+
+    async fn replace_all(_records: Vec<Self>, _client: &SupabaseClient) -> Result<()> {
+        anyhow::bail!("replace_all not supported for tournament brackets")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner(n: u8) -> AccountOwner {
+        AccountOwner::Address20([n; 20])
+    }
+
+    /// `participants` the way the application returns them
+    fn encode(participants: &Participants) -> String {
+        general_purpose::STANDARD.encode(postcard::to_allocvec(participants).unwrap())
+    }
+
+    /// The scores in the `standings_json` column, in stored order
+    fn stored_scores(participants: &Participants) -> Vec<u64> {
+        let record = participants.for_db("t1".to_string()).unwrap();
+        record
+            .standings_json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|standing| standing["score"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn swiss_bracket_decodes() {
+        let participants = Participants::Swiss(SwissParticipants {
+            players: vec![
+                SwissPlayer {
+                    player_id: owner(1),
+                    score: 1,
+                    opponents: vec!["p2".to_string()],
+                },
+                SwissPlayer {
+                    player_id: owner(2),
+                    score: 2,
+                    opponents: vec!["p1".to_string()],
+                },
+            ],
+            max_players: 8,
+        });
+
+        let decoded = Participants::decode(&encode(&participants)).unwrap();
+        assert_eq!(decoded, participants);
+        assert_eq!(decoded.format(), "swiss");
+        assert_eq!(
+            decoded.scores().collect::<Vec<_>>(),
+            [(owner(1), 1), (owner(2), 2)]
+        );
+        assert_eq!(stored_scores(&decoded), [2, 1]);
+    }
+
+    #[test]
+    fn single_elim_bracket_decodes() {
+        let participants = Participants::SingleElim(SingleElimParticipants {
+            players: vec![
+                SingleElimPlayer {
+                    player_id: owner(1),
+                    score: 0,
+                    opponents: Vec::new(),
+                },
+                SingleElimPlayer {
+                    player_id: owner(2),
+                    score: 3,
+                    opponents: vec!["p1".to_string(), "p3".to_string()],
+                },
+            ],
+            max_players: 4,
+        });
+
+        let decoded = Participants::decode(&encode(&participants)).unwrap();
+        assert_eq!(decoded, participants);
+        assert_eq!(decoded.format(), "single_elim");
+        assert_eq!(stored_scores(&decoded), [3, 0]);
+    }
+
+    #[test]
+    fn invalid_blob_is_rejected() {
+        assert!(Participants::decode("not base64!").is_err());
+        assert!(Participants::decode(&general_purpose::STANDARD.encode([9, 9, 9])).is_err());
+    }
+}
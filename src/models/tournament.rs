@@ -1,10 +1,34 @@
+use crate::models::match_history::Player;
 use crate::supabase::{SupabaseClient, SupabaseModel};
 use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// A point in time as microseconds since the Unix epoch, the unit of Linera
+/// block timestamps
+pub type Micros = u64;
 
 impl Tournament {
     pub fn for_db(&self) -> TournamentDB {
+        // An end time of 0 means none was scheduled.
+        if self.end_time != 0 && self.end_time < self.starting_time {
+            tracing::warn!(
+                "Tournament {} ends at {} before it starts at {}",
+                self.tournament_id,
+                self.end_time,
+                self.starting_time
+            );
+        }
+        if let Some(tc) = &self.time_control {
+            if let Err(e) = tc.check_label() {
+                tracing::warn!("Tournament {}: {}", self.tournament_id, e);
+            }
+        }
+        if let Err(e) = self.check_prize() {
+            tracing::warn!("Tournament {}: {}", self.tournament_id, e);
+        }
+
         TournamentDB {
             tournament_id: self.tournament_id.clone(),
             organiser_chain: self.organiser_chain.clone(),
@@ -48,7 +72,6 @@ impl Tournament {
             banner_image_url: self.banner_image_url.clone(),
             sponsor_logo_url: self.sponsor_logo_url.clone(),
 
-            // JSONB arrays
             prize_type: self.prize_type.clone(),
             prize_pool: self.prize_pool,
             custom_tags: self.custom_tags.clone(),
@@ -59,6 +82,39 @@ impl Tournament {
             status: self.status.clone(),
         }
     }
+
+    /// Checks that the prize type, if any, is a known [`PrizeType`], and that
+    /// a positive prize pool has one.
+    ///
+    /// # Errors
+    /// If the prize type is unknown, or missing while the pool isn't empty.
+    pub fn check_prize(&self) -> Result<()> {
+        let prize_type = self
+            .prize_type
+            .as_deref()
+            .filter(|prize_type| !prize_type.trim().is_empty());
+        match prize_type {
+            Some(prize_type) => {
+                prize_type.parse::<PrizeType>()?;
+            }
+            None if self.prize_pool > 0 => {
+                anyhow::bail!("prize pool of {} has no prize type", self.prize_pool)
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Compares everything except `updated_at` and `version`, which tick on
+    /// every block even when nothing meaningful changed.
+    pub fn semantically_equal(&self, other: &Tournament) -> bool {
+        let normalized = Tournament {
+            version: other.version.clone(),
+            updated_at: other.updated_at,
+            ..self.clone()
+        };
+        normalized == *other
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -84,8 +140,8 @@ pub struct TournamentDB {
     pub max_players: Option<u32>,
     pub min_players: Option<u32>,
 
-    pub starting_time: usize,
-    pub end_time: usize,
+    pub starting_time: Micros,
+    pub end_time: Micros,
 
     pub prize_pool_description: Option<String>,
 
@@ -94,13 +150,17 @@ pub struct TournamentDB {
     pub banner_image_url: Option<String>,
     pub sponsor_logo_url: Option<String>,
 
+    /// Sent as a JSON string or `null`, for a `text` column
     pub prize_type: Option<String>,
     pub prize_pool: u32,
+    /// Always sent as a JSON array of strings, `[]` when there are none.
+    /// PostgREST stores that as-is in a `jsonb` column and converts it for a
+    /// `text[]` one; a plain `text` column would get the stringified array.
     pub custom_tags: Vec<String>,
 
     pub version: String,
-    pub created_at: usize,
-    pub updated_at: usize,
+    pub created_at: Micros,
+    pub updated_at: Micros,
     pub status: String,
 }
 
@@ -112,6 +172,122 @@ pub struct TimeControl {
     pub mode_label: Option<String>, // optional human readable like "3+2"
 }
 
+impl TimeControl {
+    /// Parses a `"min+inc"` label such as `"3+2"`, keeping it as the mode label.
+    ///
+    /// # Errors
+    /// If the label isn't two non-negative integers joined by `+`.
+    pub fn from_label(label: &str) -> Result<Self> {
+        let (base, increment) = label
+            .trim()
+            .split_once('+')
+            .ok_or_else(|| anyhow::anyhow!("time control {:?} is not `min+inc`", label))?;
+        let parse = |part: &str, what: &str| {
+            part.trim()
+                .parse::<u32>()
+                .map_err(|e| anyhow::anyhow!("invalid {} in time control {:?}: {}", what, label, e))
+        };
+        Ok(TimeControl {
+            base_minutes: parse(base, "minutes")?,
+            increment_seconds: parse(increment, "increment")?,
+            mode_label: Some(label.to_string()),
+        })
+    }
+
+    /// The canonical `"min+inc"` label of the numeric fields.
+    pub fn to_label(&self) -> String {
+        format!("{}+{}", self.base_minutes, self.increment_seconds)
+    }
+
+    /// Checks that the mode label, if any, parses to the numeric fields.
+    ///
+    /// # Errors
+    /// If the label is malformed or contradicts the numbers.
+    pub fn check_label(&self) -> Result<()> {
+        let Some(label) = &self.mode_label else {
+            return Ok(());
+        };
+        let parsed = TimeControl::from_label(label)?;
+        if (parsed.base_minutes, parsed.increment_seconds)
+            != (self.base_minutes, self.increment_seconds)
+        {
+            anyhow::bail!(
+                "time control label {:?} contradicts {}",
+                label,
+                self.to_label()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = anyhow::Error;
+
+    fn from_str(label: &str) -> Result<Self> {
+        TimeControl::from_label(label)
+    }
+}
+
+/// What a tournament's prize pool is paid in, the values `prizeType` may take
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrizeType {
+    Token,
+    Fiat,
+    Nft,
+    Points,
+}
+
+impl PrizeType {
+    pub const ALL: [PrizeType; 4] = [
+        PrizeType::Token,
+        PrizeType::Fiat,
+        PrizeType::Nft,
+        PrizeType::Points,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrizeType::Token => "token",
+            PrizeType::Fiat => "fiat",
+            PrizeType::Nft => "nft",
+            PrizeType::Points => "points",
+        }
+    }
+}
+
+impl std::fmt::Display for PrizeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PrizeType {
+    type Err = anyhow::Error;
+
+    /// Parses a prize type, ignoring case and surrounding whitespace.
+    fn from_str(raw: &str) -> Result<Self> {
+        let normalized = raw.trim().to_ascii_lowercase();
+        PrizeType::ALL
+            .into_iter()
+            .find(|prize_type| prize_type.as_str() == normalized)
+            .ok_or_else(|| {
+                let known: Vec<_> = PrizeType::ALL.iter().map(PrizeType::as_str).collect();
+                anyhow::anyhow!(
+                    "unknown prize type {:?}, expected one of {}",
+                    raw,
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+/// A tournament as returned by the application's `allTournaments` query.
+///
+/// Services are upgraded separately from the watcher, so a payload may lack
+/// fields added since. New fields must therefore be an `Option` (missing
+/// means `None`) or carry `#[serde(default)]`, never a bare required type,
+/// or a single older service breaks the whole tournaments sync.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Tournament {
@@ -132,8 +308,10 @@ pub struct Tournament {
     pub min_players: Option<u32>,
 
     // --- Schedule ---
-    pub starting_time: usize,
-    pub end_time: usize,
+    pub starting_time: Micros,
+    /// 0 when unset
+    #[serde(default)]
+    pub end_time: Micros,
 
     // --- Rewards ---
     pub prize_type: Option<String>,
@@ -146,12 +324,14 @@ pub struct Tournament {
     // --- Branding ---
     pub banner_image_url: Option<String>,
     pub sponsor_logo_url: Option<String>,
+    #[serde(default)]
     pub custom_tags: Vec<String>,
 
     // --- System Metadata ---
+    #[serde(default)]
     pub version: String,
-    pub created_at: usize,
-    pub updated_at: usize,
+    pub created_at: Micros,
+    pub updated_at: Micros,
     pub status: String,
 }
 
@@ -166,15 +346,6 @@ pub struct Tournaments {
     pub all_tournaments: Vec<Tournament>,
 }
 
-pub const QUERY_TOURNAMENTS: &str = r#"{ "query": "query { allTournaments { organiserChain organiserId organiserName tournamentId tournamentName tournamentFormat matchType gameMode timeControl { baseMinutes incrementSeconds modeLabel } bannerImageUrl sponsorLogoUrl maxPlayers minPlayers startingTime endTime prizeType prizePoolDescription prizePool visibility customTags version createdAt updatedAt status } }" }"#;
-
-pub fn participants_query(tournament_id: &str) -> String {
-    format!(
-        r#"{{"query": "query {{ participants(tournamentId: \"{}\") {{ id player {{ name elo matches ath }} }} }}"}}"#,
-        tournament_id
-    )
-}
-
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct PlayerInfo {
     pub name: Option<String>,
@@ -183,6 +354,28 @@ pub struct PlayerInfo {
     pub ath: u32,
 }
 
+impl PlayerInfo {
+    /// Checks that the stats are internally consistent: the all-time high can't
+    /// be below the current elo, and a player without matches can't have moved
+    /// away from their all-time high.
+    ///
+    /// # Errors
+    /// Describing the first violated invariant.
+    pub fn validate(&self) -> Result<()> {
+        if self.ath < self.elo {
+            anyhow::bail!("ath {} is below elo {}", self.ath, self.elo);
+        }
+        if self.matches == 0 && self.ath != self.elo {
+            anyhow::bail!(
+                "ath {} differs from elo {} with no matches played",
+                self.ath,
+                self.elo
+            );
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub struct TournamentParticipant {
     pub id: String,
@@ -223,6 +416,24 @@ impl TournamentParticipant {
     }
 }
 
+impl From<&TournamentParticipant> for Player {
+    fn from(participant: &TournamentParticipant) -> Self {
+        Player {
+            id: participant.id.clone(),
+            name: participant.player.name.clone(),
+        }
+    }
+}
+
+impl From<TournamentParticipant> for Player {
+    fn from(participant: TournamentParticipant) -> Self {
+        Player {
+            id: participant.id,
+            name: participant.player.name,
+        }
+    }
+}
+
 #[async_trait]
 impl SupabaseModel for TournamentDB {
     fn table_name() -> &'static str {
@@ -233,12 +444,76 @@ impl SupabaseModel for TournamentDB {
         "tournament_id"
     }
 
-    async fn insert(&self, client: &SupabaseClient) -> Result<()> {
-        client.upsert(self).await
+    fn columns() -> &'static [&'static str] {
+        &[
+            "tournament_id",
+            "organiserChain",
+            "organiserId",
+            "organiserName",
+            "tournamentName",
+            "tournamentDescription",
+            "tournamentFormat",
+            "matchType",
+            "gameMode",
+            "timeControlBaseMinutes",
+            "timeControlIncrementSeconds",
+            "timeControlModeLabel",
+            "maxPlayers",
+            "minPlayers",
+            "startingTime",
+            "endTime",
+            "prizePoolDescription",
+            "visibility",
+            "bannerImageUrl",
+            "sponsorLogoUrl",
+            "prizeType",
+            "prizePool",
+            "customTags",
+            "version",
+            "createdAt",
+            "updatedAt",
+            "status",
+        ]
+    }
+
+    fn schema() -> &'static str {
+        r#"create table if not exists tournaments (
+            tournament_id text primary key,
+            "organiserChain" text not null,
+            "organiserId" text not null,
+            "organiserName" text not null,
+            "tournamentName" text not null,
+            "tournamentDescription" text,
+            "tournamentFormat" text not null,
+            "matchType" text not null,
+            "gameMode" text not null,
+            "timeControlBaseMinutes" integer not null,
+            "timeControlIncrementSeconds" integer not null,
+            "timeControlModeLabel" text,
+            "maxPlayers" integer,
+            "minPlayers" integer,
+            "startingTime" bigint not null,
+            "endTime" bigint not null,
+            "prizePoolDescription" text,
+            visibility text not null,
+            "bannerImageUrl" text,
+            "sponsorLogoUrl" text,
+            "prizeType" text,
+            "prizePool" bigint not null,
+            "customTags" jsonb not null default '[]',
+            version text not null,
+            "createdAt" bigint not null,
+            "updatedAt" bigint not null,
+            status text not null
+        )"#
     }
 
-    async fn insert_many(records: Vec<Self>, client: &SupabaseClient) -> Result<()> {
-        client.insert_many(&records).await
+    fn immutable_columns() -> &'static [&'static str] {
+        &["createdAt"]
+    }
+
+    async fn insert(&self, client: &SupabaseClient) -> Result<()> {
+        self.upsert(client).await
     }
 
     async fn replace(&self, client: &SupabaseClient) -> Result<()> {
@@ -264,12 +539,30 @@ impl SupabaseModel for TournamentParticipantDB {
         "id"
     }
 
-    async fn insert(&self, client: &SupabaseClient) -> Result<()> {
-        client.upsert(self).await
+    fn columns() -> &'static [&'static str] {
+        &[
+            "id",
+            "tournament_id",
+            "player_name",
+            "player_elo",
+            "player_matches",
+            "player_ath",
+        ]
     }
 
-    async fn insert_many(records: Vec<Self>, client: &SupabaseClient) -> Result<()> {
-        client.insert_many(&records).await
+    fn schema() -> &'static str {
+        r#"create table if not exists tournament_participants (
+            id text primary key,
+            tournament_id text not null,
+            player_name text,
+            player_elo integer not null,
+            player_matches integer not null,
+            player_ath integer not null
+        )"#
+    }
+
+    async fn insert(&self, client: &SupabaseClient) -> Result<()> {
+        self.upsert(client).await
     }
 
     async fn replace(&self, client: &SupabaseClient) -> Result<()> {
@@ -284,3 +577,256 @@ impl SupabaseModel for TournamentParticipantDB {
         anyhow::bail!("replace_all not supported for participants")
     }
 }
+
+#[cfg(test)]
+impl Tournament {
+    /// A tournament with consistent fields, for tests.
+    pub(crate) fn sample(tournament_id: &str) -> Self {
+        Tournament {
+            organiser_chain: "organiser-chain".to_string(),
+            organiser_id: "organiser".to_string(),
+            organiser_name: "Organiser".to_string(),
+            tournament_id: tournament_id.to_string(),
+            tournament_name: format!("Tournament {}", tournament_id),
+            tournament_description: None,
+            tournament_format: "Swiss".to_string(),
+            match_type: "Rated".to_string(),
+            game_mode: "Blitz".to_string(),
+            time_control: Some(TimeControl {
+                base_minutes: 3,
+                increment_seconds: 2,
+                mode_label: Some("3+2".to_string()),
+            }),
+            max_players: Some(16),
+            min_players: Some(2),
+            starting_time: 1_000_000,
+            end_time: 2_000_000,
+            prize_type: Some("token".to_string()),
+            prize_pool_description: None,
+            prize_pool: 100,
+            visibility: "public".to_string(),
+            banner_image_url: None,
+            sponsor_logo_url: None,
+            custom_tags: Vec::new(),
+            version: "1".to_string(),
+            created_at: 500_000,
+            updated_at: 500_000,
+            status: "open".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prize_pool_without_type_is_rejected() {
+        for prize_type in [None, Some(""), Some("  ")] {
+            let tournament = Tournament {
+                prize_type: prize_type.map(str::to_string),
+                prize_pool: 100,
+                ..Tournament::sample("t1")
+            };
+            let error = tournament.check_prize().unwrap_err();
+            assert!(error.to_string().contains("has no prize type"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn empty_pool_needs_no_type() {
+        let tournament = Tournament {
+            prize_type: None,
+            prize_pool: 0,
+            ..Tournament::sample("t1")
+        };
+        assert!(tournament.check_prize().is_ok());
+    }
+
+    #[test]
+    fn unknown_prize_type_is_rejected() {
+        let tournament = Tournament {
+            prize_type: Some("gold".to_string()),
+            ..Tournament::sample("t1")
+        };
+        let error = tournament.check_prize().unwrap_err();
+        assert!(
+            error.to_string().contains("unknown prize type"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn known_prize_types_are_accepted_in_any_case() {
+        for prize_type in ["token", "Fiat", " NFT ", "points"] {
+            let tournament = Tournament {
+                prize_type: Some(prize_type.to_string()),
+                ..Tournament::sample("t1")
+            };
+            assert!(tournament.check_prize().is_ok(), "{:?}", prize_type);
+        }
+    }
+
+    fn time_control(base_minutes: u32, increment_seconds: u32, label: &str) -> TimeControl {
+        TimeControl {
+            base_minutes,
+            increment_seconds,
+            mode_label: Some(label.to_string()),
+        }
+    }
+
+    #[test]
+    fn label_parses_into_the_numbers() {
+        assert_eq!(
+            TimeControl::from_label("3+2").unwrap(),
+            time_control(3, 2, "3+2")
+        );
+        assert_eq!(
+            " 10 + 0 ".parse::<TimeControl>().unwrap(),
+            time_control(10, 0, " 10 + 0 ")
+        );
+    }
+
+    #[test]
+    fn malformed_labels_are_rejected() {
+        for label in [
+            "", "3", "3-2", "3+", "+2", "a+2", "3+b", "3+-1", "3+2+1", "3.5+2",
+        ] {
+            assert!(TimeControl::from_label(label).is_err(), "{:?}", label);
+        }
+    }
+
+    #[test]
+    fn label_round_trips() {
+        let parsed = TimeControl::from_label("15+10").unwrap();
+        assert_eq!(parsed.to_label(), "15+10");
+    }
+
+    #[test]
+    fn label_must_match_the_numbers() {
+        assert!(time_control(3, 2, "3+2").check_label().is_ok());
+        let error = time_control(3, 2, "5+0").check_label().unwrap_err();
+        assert!(error.to_string().contains("contradicts 3+2"), "{}", error);
+        assert!(time_control(3, 2, "blitz").check_label().is_err());
+        let unlabelled = TimeControl {
+            mode_label: None,
+            ..time_control(3, 2, "")
+        };
+        assert!(unlabelled.check_label().is_ok());
+    }
+
+    #[test]
+    fn contradicting_label_is_still_written() {
+        let tournament = Tournament {
+            time_control: Some(time_control(3, 2, "5+0")),
+            ..Tournament::sample("t1")
+        };
+        let record = tournament.for_db();
+        assert_eq!(
+            (
+                record.time_control_base_minutes,
+                record.time_control_increment_seconds
+            ),
+            (3, 2)
+        );
+        assert_eq!(record.time_control_mode_label.as_deref(), Some("5+0"));
+    }
+
+    fn player(elo: u32, matches: u32, ath: u32) -> PlayerInfo {
+        PlayerInfo {
+            name: None,
+            elo,
+            matches,
+            ath,
+        }
+    }
+
+    #[test]
+    fn consistent_stats_are_valid() {
+        assert!(player(1200, 0, 1200).validate().is_ok());
+        assert!(player(1200, 5, 1200).validate().is_ok());
+        assert!(player(1150, 5, 1300).validate().is_ok());
+    }
+
+    #[test]
+    fn ath_below_elo_is_invalid() {
+        let error = player(1300, 5, 1200).validate().unwrap_err();
+        assert!(error.to_string().contains("below elo"), "{}", error);
+    }
+
+    #[test]
+    fn moving_away_from_ath_without_matches_is_invalid() {
+        let error = player(1200, 0, 1300).validate().unwrap_err();
+        assert!(error.to_string().contains("no matches played"), "{}", error);
+    }
+
+    #[test]
+    fn payload_without_late_added_fields_deserializes() {
+        let mut payload = serde_json::to_value(Tournament::sample("t1")).unwrap();
+        let object = payload.as_object_mut().unwrap();
+        for field in ["endTime", "customTags", "version"] {
+            assert!(object.remove(field).is_some(), "{} not serialized", field);
+        }
+
+        let tournament: Tournament = serde_json::from_value(payload).unwrap();
+        assert_eq!(
+            tournament,
+            Tournament {
+                end_time: 0,
+                custom_tags: Vec::new(),
+                version: String::new(),
+                ..Tournament::sample("t1")
+            }
+        );
+    }
+
+    #[test]
+    fn record_serializes_to_the_documented_json() {
+        let tournament = Tournament {
+            custom_tags: vec!["beginner".to_string(), "weekend".to_string()],
+            ..Tournament::sample("t1")
+        };
+        assert_eq!(
+            serde_json::to_string(&tournament.for_db()).unwrap(),
+            concat!(
+                r#"{"tournament_id":"t1","organiserChain":"organiser-chain","#,
+                r#""organiserId":"organiser","organiserName":"Organiser","#,
+                r#""tournamentName":"Tournament t1","tournamentDescription":null,"#,
+                r#""tournamentFormat":"Swiss","matchType":"Rated","gameMode":"Blitz","#,
+                r#""timeControlBaseMinutes":3,"timeControlIncrementSeconds":2,"#,
+                r#""timeControlModeLabel":"3+2","maxPlayers":16,"minPlayers":2,"#,
+                r#""startingTime":1000000,"endTime":2000000,"prizePoolDescription":null,"#,
+                r#""visibility":"public","bannerImageUrl":null,"sponsorLogoUrl":null,"#,
+                r#""prizeType":"token","prizePool":100,"customTags":["beginner","weekend"],"#,
+                r#""version":"1","createdAt":500000,"updatedAt":500000,"status":"open"}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn missing_tags_and_prize_type_serialize_as_empty_array_and_null() {
+        let tournament = Tournament {
+            prize_type: None,
+            prize_pool: 0,
+            ..Tournament::sample("t1")
+        };
+        let json = serde_json::to_string(&tournament.for_db()).unwrap();
+        assert!(json.contains(r#""prizeType":null,"#), "{}", json);
+        assert!(json.contains(r#""customTags":[],"#), "{}", json);
+    }
+
+    #[test]
+    fn upserts_keep_created_at_through_a_trigger() {
+        let statements = TournamentDB::preserve_statements();
+        assert_eq!(statements.len(), 3);
+        assert!(
+            statements[0].contains(r#"new."createdAt" := old."createdAt";"#),
+            "{}",
+            statements[0]
+        );
+        assert!(statements[2]
+            .starts_with(r#"create trigger keep_immutable before update on "tournaments""#));
+        assert!(TournamentParticipantDB::preserve_statements().is_empty());
+    }
+}
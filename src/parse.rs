@@ -0,0 +1,26 @@
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+
+/// Deserializes a GraphQL response body.
+///
+/// Fields that `T` doesn't know about are ignored, unless `strict` is set, in
+/// which case they are reported as an error naming each path, e.g.
+/// `data.allTournaments.0.newField`. This surfaces schema drift between the
+/// application service and these structs instead of masking it.
+///
+/// Deserialization errors name the JSON path of the offending field, e.g.
+/// `data.allTournaments[3].timeControl.baseMinutes`.
+pub fn parse_response<T: DeserializeOwned>(body: &str, strict: bool) -> Result<T> {
+    let mut deserializer = serde_json::Deserializer::from_str(body);
+    let mut unknown = Vec::new();
+    let ignored =
+        serde_ignored::Deserializer::new(&mut deserializer, |path| unknown.push(path.to_string()));
+    let value: T = serde_path_to_error::deserialize(ignored)
+        .map_err(|e| anyhow::anyhow!("at `{}`: {}", e.path(), e.inner()))?;
+    deserializer.end()?;
+
+    if strict && !unknown.is_empty() {
+        anyhow::bail!("unknown field(s) in response: {}", unknown.join(", "));
+    }
+    Ok(value)
+}
@@ -2,6 +2,8 @@ use std::time::Duration;
 
 use sysinfo::{Pid, System};
 
+use crate::metrics::METRICS;
+
 pub fn start_resource_logger() {
     tokio::spawn(async move {
         let pid = std::process::id();
@@ -15,6 +17,7 @@ pub fn start_resource_logger() {
                 let cpu = proc.cpu_usage();
                 println!("[STATS] CPU: {:.2}% | Memory: {:.2} MB", cpu, mem_mb);
             }
+            println!("[METRICS] {}", METRICS.summary());
 
             tokio::time::sleep(Duration::from_secs(5)).await;
         }
@@ -0,0 +1,134 @@
+//! `ensure-schema`: creates the tables the watcher writes to, so a fresh
+//! Supabase project needs no manual SQL.
+//!
+//! PostgREST can't run DDL itself, so the statements of
+//! [`SupabaseModel::schema`] go through a SQL function that must exist in the
+//! project, see the README. Every statement is `create table if not exists`
+//! or replaces its previous version, so running this again is harmless.
+
+use anyhow::Result;
+use serde_json::json;
+
+use crate::logging::{mark_err, mark_ok};
+use crate::models::participants::ParticipantsDB;
+use crate::models::tournament::{TournamentDB, TournamentParticipantDB};
+use crate::models::{GameCount, LeaderboardDB, MatchHistoryDB};
+use crate::supabase::{SupabaseClient, SupabaseConfig, SupabaseModel};
+
+/// What `ensure-schema` needs from the command line
+pub struct EnsureSchemaArgs {
+    pub supabase: SupabaseConfig,
+    /// Postgres function executing its `sql` argument
+    pub function: String,
+    /// Create missing tables instead of only listing them
+    pub apply: bool,
+}
+
+/// What became of a table
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableStatus {
+    Present,
+    /// Missing, and left so without `--apply`
+    Missing,
+    Created,
+}
+
+/// Checks `T`'s table and, with `apply`, creates it when missing. With
+/// `apply`, also (re)installs the trigger keeping its immutable columns, on
+/// existing tables too, since they may predate it.
+async fn ensure<T: SupabaseModel>(
+    client: &SupabaseClient,
+    args: &EnsureSchemaArgs,
+) -> Result<TableStatus> {
+    let status = if client.table_exists::<T>().await? {
+        TableStatus::Present
+    } else if !args.apply {
+        return Ok(TableStatus::Missing);
+    } else {
+        client
+            .rpc(&args.function, &json!({ "sql": T::schema() }))
+            .await?;
+        TableStatus::Created
+    };
+    if args.apply {
+        for statement in T::preserve_statements() {
+            client
+                .rpc(&args.function, &json!({ "sql": statement }))
+                .await?;
+        }
+    }
+    Ok(status)
+}
+
+/// Prints the outcome for `T`'s table, and its statement when it is missing.
+async fn report<T: SupabaseModel>(
+    client: &SupabaseClient,
+    args: &EnsureSchemaArgs,
+) -> Option<TableStatus> {
+    let table = T::table_name();
+    let status = match ensure::<T>(client, args).await {
+        Ok(status) => status,
+        Err(e) => {
+            println!("{} {}: {:#}", mark_err(), table, e);
+            return None;
+        }
+    };
+    match status {
+        TableStatus::Present => println!("{} {} already present", mark_ok(), table),
+        TableStatus::Created => println!("{} {} created", mark_ok(), table),
+        TableStatus::Missing => {
+            println!("{} {} missing:\n{};", mark_err(), table, T::schema());
+            for statement in T::preserve_statements() {
+                println!("{};", statement);
+            }
+        }
+    }
+    Some(status)
+}
+
+/// Checks every table and creates the missing ones with `args.apply`.
+///
+/// # Errors
+/// If Supabase isn't configured, or a table couldn't be checked or created.
+pub async fn run(args: EnsureSchemaArgs) -> Result<()> {
+    let client = SupabaseClient::with_config(args.supabase.clone())?;
+
+    let statuses = [
+        report::<TournamentDB>(&client, &args).await,
+        report::<TournamentParticipantDB>(&client, &args).await,
+        report::<ParticipantsDB>(&client, &args).await,
+        report::<LeaderboardDB>(&client, &args).await,
+        report::<MatchHistoryDB>(&client, &args).await,
+        report::<GameCount>(&client, &args).await,
+    ];
+
+    let count = |status| statuses.iter().filter(|s| **s == Some(status)).count();
+    if count(TableStatus::Created) > 0 {
+        // PostgREST only serves the new tables once its schema cache is reloaded.
+        client
+            .rpc(
+                &args.function,
+                &json!({ "sql": "notify pgrst, 'reload schema'" }),
+            )
+            .await?;
+    }
+
+    let failures = statuses.iter().filter(|s| s.is_none()).count();
+    if failures > 0 {
+        anyhow::bail!("{} table(s) could not be checked or created", failures);
+    }
+    let missing = count(TableStatus::Missing);
+    if missing > 0 {
+        println!(
+            "{} table(s) missing, run again with --apply to create them",
+            missing
+        );
+    } else {
+        println!(
+            "{} table(s) created, {} already present",
+            count(TableStatus::Created),
+            count(TableStatus::Present)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! HTTP endpoints for orchestrators and monitoring: `/health` and `/metrics`.
+//!
+//! Under `chain-service`, `/health` also lists the tournament chains running.
+//!
+//! Every error, including unknown routes, is answered with a JSON body of the
+//! shape `{ "error": "...", "code": 503 }` so tooling can parse failures.
+
+use anyhow::{Context, Result};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tracing::info;
+
+use crate::client_manager::ChainClientManager;
+use crate::health;
+use crate::metrics::METRICS;
+
+/// An HTTP error, rendered as `{ "error": message, "code": status }`
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// The JSON body sent for this error.
+    pub fn body(&self) -> serde_json::Value {
+        serde_json::json!({ "error": self.message, "code": self.status.as_u16() })
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body())).into_response()
+    }
+}
+
+/// Serves the endpoints on `addr` until the process exits. `max_lag` is the
+/// lag above which `/health` reports unhealthy, `None` if it never does.
+/// With `chains`, `/health` lists its running chains.
+///
+/// # Errors
+/// If `addr` can't be bound.
+pub async fn spawn(
+    addr: SocketAddr,
+    max_lag: Option<Duration>,
+    chains: Option<ChainClientManager>,
+) -> Result<()> {
+    let app = Router::new()
+        .route(
+            "/health",
+            get(move || health_handler(max_lag, chains.clone())),
+        )
+        .route("/metrics", get(|| async { METRICS.render() }))
+        .fallback(|| async { ApiError::new(StatusCode::NOT_FOUND, "no such endpoint") });
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind the HTTP server to {}", addr))?;
+    info!("Serving /health and /metrics on {}", addr);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("HTTP server stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn health_handler(
+    max_lag: Option<Duration>,
+    chains: Option<ChainClientManager>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let lag = health::lag();
+    if max_lag.is_some_and(|max_lag| !health::is_healthy(max_lag)) {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!(
+                "no successful sync for {}s despite new notifications",
+                lag.as_secs()
+            ),
+        ));
+    }
+    let mut body = serde_json::json!({ "status": "ok", "lag_secs": lag.as_secs() });
+    if let Some(chains) = chains {
+        let mut ids: Vec<String> = chains
+            .running_chains()
+            .await
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        ids.sort();
+        body["chains"] = serde_json::json!(ids);
+    }
+    Ok(Json(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn error_is_answered_with_a_json_body() {
+        let response =
+            ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "lock poisoned").into_response();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let content_type = response.headers()[axum::http::header::CONTENT_TYPE].clone();
+        assert_eq!(content_type, "application/json");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "error": "lock poisoned", "code": 503 })
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use std::future::Future;
+use std::time::Duration;
+use tracing::info;
+
+/// Upper bound on each shutdown phase, so a stuck phase can't keep the
+/// process alive forever
+const PHASE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix.
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => tracing::warn!("Can't listen for SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        tracing::warn!("Can't listen for Ctrl-C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Runs one shutdown phase, giving up on it after [`PHASE_TIMEOUT`] so the
+/// next phases still run.
+pub async fn phase(name: &str, fut: impl Future<Output = ()>) {
+    info!("Shutdown: {}", name);
+    if tokio::time::timeout(PHASE_TIMEOUT, fut).await.is_err() {
+        tracing::warn!(
+            "Shutdown: {} timed out after {:?}, moving on",
+            name,
+            PHASE_TIMEOUT
+        );
+    }
+}
@@ -1,39 +1,366 @@
 // Copyright (c) Zefchain Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+#[cfg(feature = "scylla")]
+use linera_views::scylla_db::{
+    ScyllaDbDatabase, ScyllaDbStoreConfig, ScyllaDbStoreError, ScyllaDbStoreInternalConfig,
+};
 use linera_views::{
+    batch::Batch,
     lru_prefix_cache::StorageCacheConfig,
-    rocks_db::{PathWithGuard, RocksDbSpawnMode, RocksDbStoreConfig, RocksDbStoreInternalConfig},
+    rocks_db::{
+        PathWithGuard, RocksDbDatabase, RocksDbSpawnMode, RocksDbStoreConfig, RocksDbStoreError,
+        RocksDbStoreInternalConfig,
+    },
+    store::{
+        KeyValueDatabase, KeyValueStoreError, ReadableKeyValueStore, WithError,
+        WritableKeyValueStore,
+    },
 };
 
-pub type Storage =
-    linera_storage::DbStorage<linera_views::rocks_db::RocksDbDatabase, linera_storage::WallClock>;
+pub type Storage = linera_storage::DbStorage<Database, linera_storage::WallClock>;
+
+/// Path of the local RocksDB directory
+pub const ROCKS_DB_PATH: &str = "./client.db";
+
+/// The database backing [`Storage`], picked at runtime by `--storage-backend`.
+/// RocksDB is always compiled in; ScyllaDB sits next to it with the `scylla` feature.
+#[derive(Clone)]
+pub enum Database {
+    RocksDb(RocksDbDatabase),
+    #[cfg(feature = "scylla")]
+    Scylla(ScyllaDbDatabase),
+}
+
+/// A store opened from a [`Database`], on the same backend.
+#[derive(Clone)]
+pub enum DatabaseStore {
+    RocksDb(<RocksDbDatabase as KeyValueDatabase>::Store),
+    #[cfg(feature = "scylla")]
+    Scylla(<ScyllaDbDatabase as KeyValueDatabase>::Store),
+}
+
+/// Connection settings for a [`Database`].
+pub enum DatabaseConfig {
+    RocksDb(RocksDbStoreConfig),
+    #[cfg(feature = "scylla")]
+    Scylla(ScyllaDbStoreConfig),
+}
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    RocksDb(RocksDbStoreError),
+    #[cfg(feature = "scylla")]
+    Scylla(ScyllaDbStoreError),
+}
+
+impl std::fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatabaseError::RocksDb(e) => e.fmt(f),
+            #[cfg(feature = "scylla")]
+            DatabaseError::Scylla(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DatabaseError::RocksDb(e) => Some(e),
+            #[cfg(feature = "scylla")]
+            DatabaseError::Scylla(e) => Some(e),
+        }
+    }
+}
+
+impl From<bcs::Error> for DatabaseError {
+    fn from(error: bcs::Error) -> Self {
+        DatabaseError::RocksDb(error.into())
+    }
+}
+
+impl KeyValueStoreError for DatabaseError {
+    const BACKEND: &'static str = "pws_database";
+}
+
+#[cfg(feature = "scylla")]
+const fn min(a: usize, b: usize) -> usize {
+    if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+/// Forwards a call to whichever backend `$value` holds, wrapping its error.
+macro_rules! dispatch {
+    ($value:expr, $inner:ident => $call:expr) => {
+        match $value {
+            DatabaseStore::RocksDb($inner) => $call.map_err(DatabaseError::RocksDb),
+            #[cfg(feature = "scylla")]
+            DatabaseStore::Scylla($inner) => $call.map_err(DatabaseError::Scylla),
+        }
+    };
+}
+
+/// Forwards an associated function to the backend `$config` selects, as `$db`.
+macro_rules! dispatch_config {
+    ($config:expr, $inner:ident, $db:ident => $call:expr) => {
+        match $config {
+            DatabaseConfig::RocksDb($inner) => {
+                type $db = RocksDbDatabase;
+                $call.map_err(DatabaseError::RocksDb)
+            }
+            #[cfg(feature = "scylla")]
+            DatabaseConfig::Scylla($inner) => {
+                type $db = ScyllaDbDatabase;
+                $call.map_err(DatabaseError::Scylla)
+            }
+        }
+    };
+}
+
+impl WithError for Database {
+    type Error = DatabaseError;
+}
+
+impl WithError for DatabaseStore {
+    type Error = DatabaseError;
+}
+
+impl ReadableKeyValueStore for DatabaseStore {
+    #[cfg(not(feature = "scylla"))]
+    const MAX_KEY_SIZE: usize = <RocksDbDatabase as KeyValueDatabase>::Store::MAX_KEY_SIZE;
+    #[cfg(feature = "scylla")]
+    const MAX_KEY_SIZE: usize = min(
+        <RocksDbDatabase as KeyValueDatabase>::Store::MAX_KEY_SIZE,
+        <ScyllaDbDatabase as KeyValueDatabase>::Store::MAX_KEY_SIZE,
+    );
+
+    fn max_stream_queries(&self) -> usize {
+        match self {
+            DatabaseStore::RocksDb(store) => store.max_stream_queries(),
+            #[cfg(feature = "scylla")]
+            DatabaseStore::Scylla(store) => store.max_stream_queries(),
+        }
+    }
+
+    async fn read_value_bytes(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DatabaseError> {
+        dispatch!(self, store => store.read_value_bytes(key).await)
+    }
+
+    async fn contains_key(&self, key: &[u8]) -> Result<bool, DatabaseError> {
+        dispatch!(self, store => store.contains_key(key).await)
+    }
+
+    async fn contains_keys(&self, keys: Vec<Vec<u8>>) -> Result<Vec<bool>, DatabaseError> {
+        dispatch!(self, store => store.contains_keys(keys).await)
+    }
+
+    async fn read_multi_values_bytes(
+        &self,
+        keys: Vec<Vec<u8>>,
+    ) -> Result<Vec<Option<Vec<u8>>>, DatabaseError> {
+        dispatch!(self, store => store.read_multi_values_bytes(keys).await)
+    }
+
+    async fn find_keys_by_prefix(&self, key_prefix: &[u8]) -> Result<Vec<Vec<u8>>, DatabaseError> {
+        dispatch!(self, store => store.find_keys_by_prefix(key_prefix).await)
+    }
+
+    async fn find_key_values_by_prefix(
+        &self,
+        key_prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, DatabaseError> {
+        dispatch!(self, store => store.find_key_values_by_prefix(key_prefix).await)
+    }
+}
+
+impl WritableKeyValueStore for DatabaseStore {
+    #[cfg(not(feature = "scylla"))]
+    const MAX_VALUE_SIZE: usize = <RocksDbDatabase as KeyValueDatabase>::Store::MAX_VALUE_SIZE;
+    #[cfg(feature = "scylla")]
+    const MAX_VALUE_SIZE: usize = min(
+        <RocksDbDatabase as KeyValueDatabase>::Store::MAX_VALUE_SIZE,
+        <ScyllaDbDatabase as KeyValueDatabase>::Store::MAX_VALUE_SIZE,
+    );
+
+    async fn write_batch(&self, batch: Batch) -> Result<(), DatabaseError> {
+        dispatch!(self, store => store.write_batch(batch).await)
+    }
+
+    async fn clear_journal(&self) -> Result<(), DatabaseError> {
+        dispatch!(self, store => store.clear_journal().await)
+    }
+}
+
+impl KeyValueDatabase for Database {
+    type Config = DatabaseConfig;
+    type Store = DatabaseStore;
+
+    fn get_name() -> String {
+        "pws database".to_string()
+    }
+
+    async fn connect(config: &DatabaseConfig, namespace: &str) -> Result<Self, DatabaseError> {
+        match config {
+            DatabaseConfig::RocksDb(config) => RocksDbDatabase::connect(config, namespace)
+                .await
+                .map(Database::RocksDb)
+                .map_err(DatabaseError::RocksDb),
+            #[cfg(feature = "scylla")]
+            DatabaseConfig::Scylla(config) => ScyllaDbDatabase::connect(config, namespace)
+                .await
+                .map(Database::Scylla)
+                .map_err(DatabaseError::Scylla),
+        }
+    }
+
+    fn open_shared(&self, root_key: &[u8]) -> Result<DatabaseStore, DatabaseError> {
+        match self {
+            Database::RocksDb(db) => db
+                .open_shared(root_key)
+                .map(DatabaseStore::RocksDb)
+                .map_err(DatabaseError::RocksDb),
+            #[cfg(feature = "scylla")]
+            Database::Scylla(db) => db
+                .open_shared(root_key)
+                .map(DatabaseStore::Scylla)
+                .map_err(DatabaseError::Scylla),
+        }
+    }
+
+    fn open_exclusive(&self, root_key: &[u8]) -> Result<DatabaseStore, DatabaseError> {
+        match self {
+            Database::RocksDb(db) => db
+                .open_exclusive(root_key)
+                .map(DatabaseStore::RocksDb)
+                .map_err(DatabaseError::RocksDb),
+            #[cfg(feature = "scylla")]
+            Database::Scylla(db) => db
+                .open_exclusive(root_key)
+                .map(DatabaseStore::Scylla)
+                .map_err(DatabaseError::Scylla),
+        }
+    }
+
+    async fn list_all(config: &DatabaseConfig) -> Result<Vec<String>, DatabaseError> {
+        dispatch_config!(config, config, Db => Db::list_all(config).await)
+    }
+
+    async fn list_root_keys(
+        config: &DatabaseConfig,
+        namespace: &str,
+    ) -> Result<Vec<Vec<u8>>, DatabaseError> {
+        dispatch_config!(config, config, Db => Db::list_root_keys(config, namespace).await)
+    }
+
+    async fn exists(config: &DatabaseConfig, namespace: &str) -> Result<bool, DatabaseError> {
+        dispatch_config!(config, config, Db => Db::exists(config, namespace).await)
+    }
+
+    async fn create(config: &DatabaseConfig, namespace: &str) -> Result<(), DatabaseError> {
+        dispatch_config!(config, config, Db => Db::create(config, namespace).await)
+    }
+
+    async fn delete(config: &DatabaseConfig, namespace: &str) -> Result<(), DatabaseError> {
+        dispatch_config!(config, config, Db => Db::delete(config, namespace).await)
+    }
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    /// Another process holds the RocksDB lock, or a crashed one left it behind
+    Locked { path: String, reason: String },
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::Locked { path, reason } => write!(
+                f,
+                "storage at {} is locked ({}). Check that no other instance is running; \
+                 if none is, a crashed process left {}/LOCK behind and it can be removed by hand",
+                path, reason, path
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
 
-/// Create and return the storage implementation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum StorageBackend {
+    /// Local RocksDB directory (default)
+    #[default]
+    RocksDb,
+    /// Shared ScyllaDB cluster, requires building with `--features scylla`
+    Scylla,
+}
+
+/// Storage selection, exposed as CLI flags
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct StorageConfig {
+    /// Database backend used for the client storage
+    #[arg(long, value_enum, default_value_t, global = true)]
+    pub storage_backend: StorageBackend,
+
+    /// ScyllaDB contact point, e.g. `localhost:9042` (with `--storage-backend scylla`)
+    #[arg(long, value_name = "URI", global = true)]
+    pub scylla_uri: Option<String>,
+}
+
+fn storage_cache_config() -> StorageCacheConfig {
+    StorageCacheConfig {
+        max_cache_size: 100000,
+        max_cache_entries: 100000,
+        max_cache_find_key_values_size: 100000,
+        max_cache_find_keys_size: 100000,
+        max_cache_value_size: 100000,
+        max_find_key_values_entry_size: 100000,
+        max_find_keys_entry_size: 100000,
+        max_value_entry_size: 100000,
+    }
+}
+
+/// Opens the storage selected by `config`.
+///
+/// # Errors
+/// If the selected backend isn't compiled in, or the storage can't be initialized.
+pub async fn connect(config: &StorageConfig) -> Result<Storage, anyhow::Error> {
+    match config.storage_backend {
+        StorageBackend::RocksDb => get_storage().await,
+        #[cfg(feature = "scylla")]
+        StorageBackend::Scylla => {
+            let uri = config
+                .scylla_uri
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("`--scylla-uri` is required for ScyllaDB"))?;
+            get_scylla_storage(uri).await
+        }
+        #[cfg(not(feature = "scylla"))]
+        StorageBackend::Scylla => {
+            anyhow::bail!("ScyllaDB storage requires building with `--features scylla`")
+        }
+    }
+}
+
+/// Create and return the RocksDB storage implementation.
 ///
 /// # Errors
 /// If the storage can't be initialized.
-pub async fn get_storage() -> Result<Storage, linera_views::ViewError> {
+pub async fn get_storage() -> Result<Storage, anyhow::Error> {
     let inner_config = RocksDbStoreInternalConfig {
-        path_with_guard: PathWithGuard::new("./linera".into()),
+        path_with_guard: PathWithGuard::new(ROCKS_DB_PATH.into()),
         spawn_mode: RocksDbSpawnMode::SpawnBlocking, // Best for tokio multi-threaded
         max_stream_queries: 20,                      // Higher for better concurrency
     };
 
-    let config = RocksDbStoreConfig {
+    let config = DatabaseConfig::RocksDb(RocksDbStoreConfig {
         inner_config,
-        storage_cache_config: StorageCacheConfig {
-            // mock values, need to find suitable values
-            max_cache_size: 100000,
-            max_cache_entries: 100000,
-            max_cache_find_key_values_size: 10000,
-            max_cache_find_keys_size: 10000,
-            max_cache_value_size: 1000,
-            max_find_key_values_entry_size: 1000,
-            max_find_keys_entry_size: 1000,
-            max_value_entry_size: 1000,
-        },
-    };
+        storage_cache_config: storage_cache_config(),
+    });
 
     let storage = linera_storage::DbStorage::maybe_create_and_connect(
         &config,
@@ -41,7 +368,47 @@ pub async fn get_storage() -> Result<Storage, linera_views::ViewError> {
         Some(linera_execution::WasmRuntime::Wasmer),
     )
     .await
-    .expect("failed to create storage");
+    .map_err(|e| {
+        let reason = e.to_string();
+        // RocksDB reports e.g. "IO error: While lock file: ./client.db/LOCK: Resource temporarily unavailable"
+        if reason.contains("lock file") || reason.contains("LOCK") {
+            anyhow::Error::new(StorageError::Locked {
+                path: ROCKS_DB_PATH.to_string(),
+                reason,
+            })
+        } else {
+            anyhow::Error::new(e).context("failed to create storage")
+        }
+    })?;
+
+    Ok(storage)
+}
+
+/// Create and return a ScyllaDB-backed storage, shared between every
+/// instance pointed at the same cluster.
+///
+/// # Errors
+/// If the cluster can't be reached or the keyspace can't be created.
+#[cfg(feature = "scylla")]
+pub async fn get_scylla_storage(uri: &str) -> Result<Storage, anyhow::Error> {
+    let inner_config = ScyllaDbStoreInternalConfig {
+        uri: uri.to_string(),
+        max_stream_queries: 20,
+        max_concurrent_queries: None,
+        replication_factor: 1,
+    };
+
+    let config = DatabaseConfig::Scylla(ScyllaDbStoreConfig {
+        inner_config,
+        storage_cache_config: storage_cache_config(),
+    });
+
+    let storage = linera_storage::DbStorage::maybe_create_and_connect(
+        &config,
+        "linera",
+        Some(linera_execution::WasmRuntime::Wasmer),
+    )
+    .await?;
 
     Ok(storage)
 }
@@ -0,0 +1,137 @@
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::metrics::METRICS;
+
+use super::SupabaseError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests flow normally.
+    Closed,
+    /// Requests are short-circuited until the cooldown elapses.
+    Open,
+    /// A single probe request is allowed through to test recovery.
+    HalfOpen,
+}
+
+impl CircuitState {
+    fn as_gauge(self) -> u64 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::Open => 1,
+            CircuitState::HalfOpen => 2,
+        }
+    }
+}
+
+struct Inner {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+}
+
+/// Stops sending requests to Supabase after `failure_threshold` consecutive
+/// failures, for `cooldown`, then lets one probe through before closing again.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                probe_in_flight: false,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Checks whether a request may be sent right now.
+    pub fn acquire(&self) -> Result<(), SupabaseError> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::Open => {
+                let elapsed = inner.opened_at.map(|t| t.elapsed()).unwrap_or_default();
+                if elapsed >= self.cooldown {
+                    Self::transition(&mut inner, CircuitState::HalfOpen);
+                    inner.probe_in_flight = true;
+                    Ok(())
+                } else {
+                    METRICS
+                        .supabase_short_circuited_total
+                        .fetch_add(1, Ordering::Relaxed);
+                    Err(SupabaseError::CircuitOpen {
+                        retry_in: self.cooldown - elapsed,
+                    })
+                }
+            }
+            CircuitState::HalfOpen => {
+                if inner.probe_in_flight {
+                    METRICS
+                        .supabase_short_circuited_total
+                        .fetch_add(1, Ordering::Relaxed);
+                    Err(SupabaseError::CircuitOpen {
+                        retry_in: Duration::ZERO,
+                    })
+                } else {
+                    inner.probe_in_flight = true;
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = 0;
+        inner.probe_in_flight = false;
+        if inner.state != CircuitState::Closed {
+            tracing::info!("[Supabase] Circuit closed, writes resumed");
+            Self::transition(&mut inner, CircuitState::Closed);
+        }
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        inner.probe_in_flight = false;
+        let should_open = match inner.state {
+            CircuitState::HalfOpen => true,
+            CircuitState::Closed => inner.consecutive_failures >= self.failure_threshold,
+            CircuitState::Open => false,
+        };
+        if should_open {
+            tracing::error!(
+                "[Supabase] Circuit opened after {} consecutive failures, pausing writes for {:?}",
+                inner.consecutive_failures,
+                self.cooldown
+            );
+            inner.opened_at = Some(Instant::now());
+            METRICS
+                .supabase_circuit_opened_total
+                .fetch_add(1, Ordering::Relaxed);
+            Self::transition(&mut inner, CircuitState::Open);
+        }
+    }
+
+    fn transition(inner: &mut Inner, state: CircuitState) {
+        inner.state = state;
+        METRICS
+            .supabase_circuit_state
+            .store(state.as_gauge(), Ordering::Relaxed);
+    }
+}
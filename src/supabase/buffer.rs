@@ -0,0 +1,201 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use super::{primary_key_value, SupabaseClient, SupabaseModel};
+
+/// Where a [`WriteBuffer`] writes its rows: [`SupabaseClient`], or a fake in tests.
+#[async_trait]
+pub trait RowSink<T>: Send + Sync {
+    async fn upsert_many(&self, records: &[T]) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: SupabaseModel> RowSink<T> for SupabaseClient {
+    async fn upsert_many(&self, records: &[T]) -> Result<()> {
+        SupabaseClient::upsert_many(self, records).await
+    }
+}
+
+/// Accumulates upserts for a single table and writes them with one request.
+///
+/// Records are coalesced by primary key, so only the latest version of a row
+/// is sent. The buffer is flushed when it reaches `threshold` rows or when the
+/// flusher spawned by [`WriteBuffer::spawn_flusher`] ticks, whichever comes first.
+pub struct WriteBuffer<T: SupabaseModel> {
+    client: Arc<dyn RowSink<T>>,
+    threshold: usize,
+    pending: Mutex<HashMap<String, T>>,
+}
+
+impl<T: SupabaseModel + 'static> WriteBuffer<T> {
+    pub fn new(client: Arc<dyn RowSink<T>>, threshold: usize) -> Arc<Self> {
+        Arc::new(Self {
+            client,
+            threshold: threshold.max(1),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Queues a record, replacing any pending record with the same primary key.
+    pub async fn push(&self, record: T) -> Result<()> {
+        let key = primary_key_value(&record)?;
+        let len = {
+            let mut pending = self.pending.lock().await;
+            pending.insert(key, record);
+            pending.len()
+        };
+
+        if len >= self.threshold {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Writes all pending records. On failure they are put back so the next
+    /// flush retries them, unless a newer version was queued in the meantime.
+    pub async fn flush(&self) -> Result<()> {
+        let batch = std::mem::take(&mut *self.pending.lock().await);
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let (keys, records): (Vec<String>, Vec<T>) = batch.into_iter().unzip();
+        match self.client.upsert_many(&records).await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let mut pending = self.pending.lock().await;
+                for (key, record) in keys.into_iter().zip(records) {
+                    pending.entry(key).or_insert(record);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Flushes the buffer every `interval` until `cancel` fires, then once
+    /// more, so rows queued since the last tick aren't lost on shutdown.
+    pub fn spawn_flusher(
+        self: &Arc<Self>,
+        interval: Duration,
+        cancel: CancellationToken,
+    ) -> JoinHandle<()> {
+        let this = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                let last = tokio::select! {
+                    _ = ticker.tick() => false,
+                    () = cancel.cancelled() => true,
+                };
+                if let Err(e) = this.flush().await {
+                    tracing::error!("Failed to flush `{}` buffer: {}", T::table_name(), e);
+                }
+                if last {
+                    return;
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::GameCount;
+
+    /// Records every batch written, failing while `fail` is set
+    #[derive(Default)]
+    struct FakeSink {
+        batches: std::sync::Mutex<Vec<Vec<(String, u64)>>>,
+        fail: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait]
+    impl RowSink<GameCount> for FakeSink {
+        async fn upsert_many(&self, records: &[GameCount]) -> Result<()> {
+            if self.fail.load(std::sync::atomic::Ordering::SeqCst) {
+                anyhow::bail!("Supabase is down");
+            }
+            let mut rows: Vec<_> = records.iter().map(|r| (r.id.clone(), r.count)).collect();
+            rows.sort();
+            self.batches.lock().unwrap().push(rows);
+            Ok(())
+        }
+    }
+
+    fn count(id: &str, count: u64) -> GameCount {
+        GameCount {
+            id: id.to_string(),
+            count,
+        }
+    }
+
+    fn written(sink: &FakeSink) -> Vec<Vec<(String, u64)>> {
+        sink.batches.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn reaching_the_threshold_flushes() {
+        let sink = Arc::new(FakeSink::default());
+        let buffer = WriteBuffer::new(sink.clone(), 2);
+
+        buffer.push(count("a", 1)).await.unwrap();
+        assert!(written(&sink).is_empty());
+        buffer.push(count("b", 2)).await.unwrap();
+        assert_eq!(
+            written(&sink),
+            [vec![("a".to_string(), 1), ("b".to_string(), 2)]]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_flush_puts_rows_back() {
+        let sink = Arc::new(FakeSink::default());
+        let buffer = WriteBuffer::new(sink.clone(), 10);
+
+        buffer.push(count("a", 1)).await.unwrap();
+        buffer.push(count("b", 1)).await.unwrap();
+        sink.fail.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(buffer.flush().await.is_err());
+
+        // A version queued after the failure wins over the one put back.
+        buffer.push(count("b", 2)).await.unwrap();
+        sink.fail.store(false, std::sync::atomic::Ordering::SeqCst);
+        buffer.flush().await.unwrap();
+        assert_eq!(
+            written(&sink),
+            [vec![("a".to_string(), 1), ("b".to_string(), 2)]]
+        );
+    }
+
+    #[tokio::test]
+    async fn last_write_wins_per_primary_key() {
+        let sink = Arc::new(FakeSink::default());
+        let buffer = WriteBuffer::new(sink.clone(), 10);
+
+        buffer.push(count("a", 1)).await.unwrap();
+        buffer.push(count("a", 3)).await.unwrap();
+        buffer.push(count("a", 2)).await.unwrap();
+        buffer.flush().await.unwrap();
+        assert_eq!(written(&sink), [vec![("a".to_string(), 2)]]);
+    }
+
+    #[tokio::test]
+    async fn cancelling_the_flusher_flushes_once_more() {
+        let sink = Arc::new(FakeSink::default());
+        let buffer = WriteBuffer::new(sink.clone(), 10);
+        let cancel = CancellationToken::new();
+        let flusher = buffer.spawn_flusher(Duration::from_secs(3600), cancel.clone());
+
+        buffer.push(count("a", 1)).await.unwrap();
+        cancel.cancel();
+        flusher.await.unwrap();
+        assert_eq!(written(&sink), [vec![("a".to_string(), 1)]]);
+    }
+}
@@ -0,0 +1,1249 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, CONTENT_RANGE, CONTENT_TYPE};
+use reqwest::{Certificate, Client, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::env;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+pub mod breaker;
+pub mod buffer;
+use breaker::CircuitBreaker;
+
+use crate::limits::check_response_size;
+use crate::logging::{mark_ok, truncate_body};
+use crate::metrics::METRICS;
+use crate::secret::Secret;
+use crate::util::Backoff;
+
+/// Consecutive failures after which the circuit opens
+const CIRCUIT_FAILURE_THRESHOLD: u32 = 5;
+/// How long the circuit stays open before probing Supabase again
+const CIRCUIT_COOLDOWN: Duration = Duration::from_secs(30);
+/// Pause before retrying a request failed by a gateway, backed off up to [`GATEWAY_MAX_DELAY`]
+const GATEWAY_RETRY_DELAY: Duration = Duration::from_millis(500);
+const GATEWAY_MAX_DELAY: Duration = Duration::from_secs(5);
+/// Longest summary kept of a non-JSON error body
+const ERROR_SUMMARY_CHARS: usize = 200;
+
+#[derive(Debug)]
+pub enum SupabaseError {
+    /// Supabase has been failing consistently and requests are paused
+    CircuitOpen { retry_in: Duration },
+    /// A request got no complete response within `--supabase-timeout`
+    Timeout { after: Duration },
+    /// The table doesn't exist in the Supabase database
+    MissingTable { table: String },
+    /// A model's column, e.g. its primary key, doesn't exist in its table
+    MissingColumn { table: String, column: String },
+    /// A proxy or gateway in front of PostgREST answered 502, 503 or 504
+    /// with its own error page, summarized
+    Gateway { status: StatusCode, summary: String },
+}
+
+impl SupabaseError {
+    /// Whether `error` is a [`SupabaseError::MissingTable`], which is warned
+    /// about once when first seen and needn't be logged again by callers.
+    pub fn is_missing_table(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<SupabaseError>(),
+            Some(SupabaseError::MissingTable { .. })
+        )
+    }
+
+    /// Whether `error` is a [`SupabaseError::Gateway`], an infrastructure
+    /// failure worth retrying rather than an error of the request itself.
+    pub fn is_gateway(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<SupabaseError>(),
+            Some(SupabaseError::Gateway { .. })
+        )
+    }
+}
+
+impl std::fmt::Display for SupabaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SupabaseError::CircuitOpen { retry_in } => write!(
+                f,
+                "Supabase circuit is open, skipping request (retry in {:?})",
+                retry_in
+            ),
+            SupabaseError::Timeout { after } => {
+                write!(f, "Supabase request timed out after {:?}", after)
+            }
+            SupabaseError::MissingTable { table } => {
+                write!(f, "Supabase table `{}` does not exist", table)
+            }
+            SupabaseError::MissingColumn { table, column } => write!(
+                f,
+                "Supabase table `{}` has no column `{}`, check the model's primary key",
+                table, column
+            ),
+            SupabaseError::Gateway { status, summary } => write!(
+                f,
+                "gateway in front of Supabase failed with {}: {}",
+                status, summary
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SupabaseError {}
+
+/// Trait representing a model that can be persisted to Supabase.
+///
+/// `insert`, `upsert` and `insert_many` default to the plain
+/// [`SupabaseClient`] call; models only override them to behave differently.
+/// `Sized`, since the default bodies pass `Self` to the generic client methods.
+#[async_trait]
+pub trait SupabaseModel: Serialize + Send + Sync + Sized {
+    /// The name of the table in Supabase
+    fn table_name() -> &'static str;
+    fn primary_key() -> &'static str;
+
+    /// Every serialized field name, in declaration order: the columns
+    /// [`SupabaseClient::select`] reads and [`SupabaseClient::update`] may write.
+    fn columns() -> &'static [&'static str];
+
+    /// Columns set on the first insert and never overwritten by upserts,
+    /// such as creation timestamps. Names are the serialized field names.
+    /// Upserts leave them out of the update, and the trigger of
+    /// [`SupabaseModel::preserve_statements`] guards against other writers.
+    fn immutable_columns() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Statements installing a `before update` trigger that puts back the
+    /// stored value of every immutable column, so an upsert's `on conflict
+    /// do update` can't change them. Empty without immutable columns. They
+    /// replace any previous version, so running them again is harmless.
+    fn preserve_statements() -> Vec<String> {
+        let columns = Self::immutable_columns();
+        if columns.is_empty() {
+            return Vec::new();
+        }
+        let table = Self::table_name();
+        let function = format!("\"{}_keep_immutable\"", table);
+        let assignments: String = columns
+            .iter()
+            .map(|column| format!("new.\"{0}\" := old.\"{0}\"; ", column))
+            .collect();
+        vec![
+            format!(
+                "create or replace function {}() returns trigger language plpgsql \
+                 as $$ begin {}return new; end $$",
+                function, assignments
+            ),
+            format!("drop trigger if exists keep_immutable on \"{}\"", table),
+            format!(
+                "create trigger keep_immutable before update on \"{}\" \
+                 for each row execute function {}()",
+                table, function
+            ),
+        ]
+    }
+
+    /// The `create table if not exists` statement for the table, with one
+    /// column per serialized field. Run by `ensure-schema`.
+    fn schema() -> &'static str;
+
+    /// Insert the record into Supabase. Models writing a row per key
+    /// override this with [`SupabaseModel::upsert`].
+    async fn insert(&self, client: &SupabaseClient) -> Result<()> {
+        client.insert(self).await
+    }
+
+    /// Insert the record, or update the row with the same primary key.
+    async fn upsert(&self, client: &SupabaseClient) -> Result<()> {
+        client.upsert(self).await
+    }
+
+    async fn insert_many(records: Vec<Self>, client: &SupabaseClient) -> Result<()>
+    where
+        Self: Sized,
+    {
+        client.insert_many(&records).await
+    }
+
+    async fn replace(&self, client: &SupabaseClient) -> Result<()>;
+
+    async fn replace_all(records: Vec<Self>, client: &SupabaseClient) -> Result<()>
+    where
+        Self: Sized;
+}
+
+/// The `return=` preference sent in the `Prefer` header of inserts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReturnPreference {
+    /// Supabase does not echo the inserted rows back.
+    #[default]
+    Minimal,
+    /// Supabase returns the inserted rows in the response body.
+    Representation,
+}
+
+impl ReturnPreference {
+    fn header_value(self) -> &'static str {
+        match self {
+            ReturnPreference::Minimal => "return=minimal",
+            ReturnPreference::Representation => "return=representation",
+        }
+    }
+}
+
+/// A PostgREST row filter on a single column
+#[derive(Clone, Debug)]
+pub enum Filter {
+    Eq(String),
+    In(Vec<String>),
+    NotIn(Vec<String>),
+}
+
+impl Filter {
+    /// Renders the filter as a PostgREST query value, e.g. `not.in.("a","b")`.
+    pub(crate) fn to_query_value(&self) -> String {
+        fn list(values: &[String]) -> String {
+            let quoted: Vec<String> = values
+                .iter()
+                .map(|v| format!("\"{}\"", v.replace('\\', "\\\\").replace('"', "\\\"")))
+                .collect();
+            format!("({})", quoted.join(","))
+        }
+
+        match self {
+            Filter::Eq(value) => format!("eq.{}", value),
+            Filter::In(values) => format!("in.{}", list(values)),
+            Filter::NotIn(values) => format!("not.in.{}", list(values)),
+        }
+    }
+}
+
+/// The table named in a PostgREST "relation does not exist" error, if that's
+/// what `body` is. PostgREST reports it as `PGRST205` (schema cache lookup)
+/// or passes through Postgres' `42P01`.
+fn missing_table(status: StatusCode, path: &str, body: &str) -> Option<String> {
+    if status.is_success() || !(body.contains("PGRST205") || body.contains("42P01")) {
+        return None;
+    }
+    let table = path.strip_prefix("/rest/v1/")?.split('/').next()?;
+    Some(table.to_string())
+}
+
+/// A one-line summary of an error body that isn't JSON, such as the HTML page
+/// of a reverse proxy: its `<title>`, or else its first non-empty line without
+/// tags. `None` for JSON bodies, which are PostgREST's own errors and are kept.
+fn summarize_error_body(content_type: Option<&str>, body: &str) -> Option<String> {
+    let trimmed = body.trim_start();
+    if content_type.is_some_and(|ct| ct.contains("json"))
+        || trimmed.starts_with('{')
+        || trimmed.starts_with('[')
+    {
+        return None;
+    }
+
+    let lower = body.to_ascii_lowercase();
+    let title = lower.find("<title>").and_then(|start| {
+        let start = start + "<title>".len();
+        let end = start + lower[start..].find("</title>")?;
+        Some(body[start..end].trim().to_string())
+    });
+    let summary = title.filter(|t| !t.is_empty()).unwrap_or_else(|| {
+        body.lines()
+            .map(strip_tags)
+            .map(|line| line.trim().to_string())
+            .find(|line| !line.is_empty())
+            .unwrap_or_else(|| "empty body".to_string())
+    });
+    Some(summary.chars().take(ERROR_SUMMARY_CHARS).collect())
+}
+
+/// `line` without anything between `<` and `>`.
+fn strip_tags(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut in_tag = false;
+    for c in line.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Serializes `records` for an upsert. With `omit_nulls`, null columns are
+/// dropped, so the upsert leaves them untouched, and rows are grouped by the
+/// columns they still have; otherwise all rows form one group.
+fn upsert_groups<T: Serialize>(records: &[T], omit_nulls: bool) -> Result<Vec<Vec<Value>>> {
+    let rows = records
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    if !omit_nulls {
+        return Ok(vec![rows]);
+    }
+
+    let mut groups: Vec<(Vec<String>, Vec<Value>)> = Vec::new();
+    for mut row in rows {
+        let mut columns = Vec::new();
+        if let Some(object) = row.as_object_mut() {
+            object.retain(|_, value| !value.is_null());
+            columns = object.keys().cloned().collect();
+        }
+        match groups.iter_mut().find(|(keys, _)| *keys == columns) {
+            Some((_, group)) => group.push(row),
+            None => groups.push((columns, vec![row])),
+        }
+    }
+    Ok(groups.into_iter().map(|(_, group)| group).collect())
+}
+
+/// Renders `filters` as URL-encoded `column=value` query parameters.
+fn filter_query(filters: &[(&str, Filter)]) -> Vec<String> {
+    filters
+        .iter()
+        .map(|(column, filter)| {
+            format!(
+                "{}={}",
+                column,
+                urlencoding::encode(&filter.to_query_value())
+            )
+        })
+        .collect()
+}
+
+/// Tunables for [`SupabaseClient`], exposed as CLI flags
+#[derive(clap::Args, Clone, Debug)]
+pub struct SupabaseConfig {
+    /// `return=` preference sent with Supabase inserts (`representation` echoes the rows back)
+    #[arg(long, value_enum, default_value_t, global = true)]
+    pub prefer: ReturnPreference,
+
+    /// Maximum number of Supabase requests in flight at once
+    #[arg(
+        long = "supabase-max-concurrency",
+        value_name = "N",
+        default_value_t = 8,
+        global = true
+    )]
+    pub max_concurrent_requests: usize,
+
+    /// Per-request Supabase timeout in seconds
+    #[arg(
+        long = "supabase-timeout",
+        value_name = "SECS",
+        default_value_t = 30,
+        global = true
+    )]
+    pub timeout_secs: u64,
+
+    /// Rows per request of `insert_many` and `replace_all`; lower it for wide rows hitting payload limits
+    #[arg(
+        long = "supabase-batch-size",
+        value_name = "N",
+        default_value_t = 500,
+        global = true
+    )]
+    pub batch_size: usize,
+
+    /// Retries of a request failed by a gateway in front of Supabase (502/503/504); 0 fails right away
+    #[arg(
+        long = "supabase-retries",
+        value_name = "N",
+        default_value_t = 3,
+        global = true
+    )]
+    pub retries: u32,
+
+    /// Let values from `.env` override variables already set in the process environment
+    #[arg(long, global = true)]
+    pub dotenv_override: bool,
+
+    /// Leave out null fields from upserts, so a momentarily missing optional
+    /// field doesn't overwrite a stored value (a field can then no longer be cleared)
+    #[arg(long, global = true)]
+    pub upsert_omit_nulls: bool,
+
+    /// PEM certificate trusted as an additional root, for self-hosted Supabase behind a private CA
+    #[arg(long, value_name = "PATH", global = true)]
+    pub supabase_ca_cert: Option<PathBuf>,
+
+    /// DANGEROUS: accept any TLS certificate from Supabase, including self-signed
+    /// and mismatched ones. Only for local development
+    #[arg(long, global = true)]
+    pub supabase_insecure_skip_verify: bool,
+}
+
+impl Default for SupabaseConfig {
+    fn default() -> Self {
+        Self {
+            prefer: ReturnPreference::default(),
+            max_concurrent_requests: 8,
+            timeout_secs: 30,
+            batch_size: 500,
+            retries: 3,
+            dotenv_override: false,
+            upsert_omit_nulls: false,
+            supabase_ca_cert: None,
+            supabase_insecure_skip_verify: false,
+        }
+    }
+}
+
+/// Loads `.env` into the process environment. Variables already set in the
+/// process win unless `override_existing` is set. Logs which source each key
+/// came from at `debug`, never the values.
+fn load_dotenv(override_existing: bool) {
+    let entries = match dotenv::dotenv_iter() {
+        Ok(entries) => entries,
+        Err(_) => {
+            debug!("No .env file, using the process environment only");
+            return;
+        }
+    };
+    for entry in entries {
+        let (key, value) = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::warn!("Skipping malformed .env line: {}", e);
+                continue;
+            }
+        };
+        if env::var_os(&key).is_some() && !override_existing {
+            debug!("{} taken from the process environment (.env ignored)", key);
+        } else {
+            env::set_var(&key, value);
+            debug!("{} taken from .env", key);
+        }
+    }
+}
+
+/// Represents a Supabase HTTP client
+pub struct SupabaseClient {
+    client: Client,
+    url: String,
+    /// Service-role key used for writes
+    key: Secret<String>,
+    /// Key used for `select`/`count`, `SUPABASE_READ_KEY` or the write key
+    read_key: Secret<String>,
+    prefer: ReturnPreference,
+    /// Leave null columns out of upserts
+    omit_nulls: bool,
+    /// Rows per `insert_many` request
+    batch_size: usize,
+    /// Retries of a request failed by a gateway, after the first attempt
+    retries: u32,
+    timeout: Duration,
+    breaker: CircuitBreaker,
+    limiter: Semaphore,
+    /// Tables already reported as missing, so the warning is logged once
+    missing_tables: Mutex<HashSet<String>>,
+}
+
+impl SupabaseClient {
+    pub fn new() -> Result<Self> {
+        Self::with_config(SupabaseConfig::default())
+    }
+
+    pub fn with_config(config: SupabaseConfig) -> Result<Self> {
+        load_dotenv(config.dotenv_override);
+        let url = env::var("SUPABASE_URL")?;
+        let key = Secret::new(env::var("SUPABASE_KEY")?);
+        let read_key = env::var("SUPABASE_READ_KEY")
+            .map(Secret::new)
+            .unwrap_or_else(|_| key.clone());
+        let timeout = Duration::from_secs(config.timeout_secs);
+
+        let mut builder = Client::builder().timeout(timeout);
+        if let Some(path) = &config.supabase_ca_cert {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read CA certificate {}", path.display()))?;
+            let cert = Certificate::from_pem(&pem)
+                .with_context(|| format!("Invalid CA certificate {}", path.display()))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if config.supabase_insecure_skip_verify {
+            tracing::warn!(
+                "TLS certificate verification is DISABLED for Supabase ({}); \
+                 anyone on the network path can read and alter its traffic",
+                url
+            );
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        tracing::info!("Supabase batch size: {} rows", config.batch_size.max(1));
+        tracing::info!("Supabase retries on gateway errors: {}", config.retries);
+
+        Ok(Self {
+            client: builder.build()?,
+            url,
+            key,
+            read_key,
+            prefer: config.prefer,
+            omit_nulls: config.upsert_omit_nulls,
+            batch_size: config.batch_size.max(1),
+            retries: config.retries,
+            timeout,
+            breaker: CircuitBreaker::new(CIRCUIT_FAILURE_THRESHOLD, CIRCUIT_COOLDOWN),
+            limiter: Semaphore::new(config.max_concurrent_requests.max(1)),
+            missing_tables: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn circuit_breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    /// Sends a request through the circuit breaker and returns its status and body.
+    ///
+    /// At most `max_concurrent_requests` requests run at once; callers beyond
+    /// that wait for a permit. Transport errors, 5xx responses and 429s count
+    /// as failures; any other response means Supabase is reachable and closes
+    /// the circuit. Requests exceeding the configured timeout fail with
+    /// [`SupabaseError::Timeout`].
+    ///
+    /// Error bodies that aren't JSON are replaced by a one-line summary, see
+    /// [`summarize_error_body`]. When they come with a 502, 503 or 504, the
+    /// request is retried up to `--supabase-retries` times, each counted in
+    /// the metrics, before failing with [`SupabaseError::Gateway`].
+    async fn execute(&self, request: RequestBuilder) -> Result<(StatusCode, String)> {
+        let (status, _, body) = self.execute_with_headers(request).await?;
+        Ok((status, body))
+    }
+
+    /// Same as `execute`, also returning the response headers.
+    async fn execute_with_headers(
+        &self,
+        mut request: RequestBuilder,
+    ) -> Result<(StatusCode, HeaderMap, String)> {
+        let backoff = Backoff::new(GATEWAY_RETRY_DELAY, GATEWAY_MAX_DELAY);
+        let attempts = self.retries.saturating_add(1);
+        let mut attempt = 1;
+        loop {
+            // Streamed bodies can't be cloned, so such requests aren't retried.
+            let retry = if attempt < attempts {
+                request.try_clone()
+            } else {
+                None
+            };
+            match self.send(request).await {
+                Err(e) if SupabaseError::is_gateway(&e) => {
+                    let Some(next) = retry else {
+                        return Err(e);
+                    };
+                    let delay = backoff.delay(attempt - 1);
+                    tracing::warn!(
+                        "{} (attempt {}/{}), retrying in {:?}",
+                        e,
+                        attempt,
+                        attempts,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    // Split to read the method, which the builder doesn't expose.
+                    let (client, next) = next.build_split();
+                    let next = next?;
+                    METRICS.supabase_retries.record(next.method());
+                    request = RequestBuilder::from_parts(client, next);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Sends `request` once, see `execute`.
+    async fn send(&self, request: RequestBuilder) -> Result<(StatusCode, HeaderMap, String)> {
+        self.breaker.acquire()?;
+        let _permit = self.limiter.acquire().await?;
+
+        let mut res = match request.send().await {
+            Ok(res) => res,
+            Err(e) => {
+                self.breaker.record_failure();
+                return Err(self.request_error(e));
+            }
+        };
+
+        let status = res.status();
+        let path = res.url().path().to_string();
+        let headers = res.headers().clone();
+        // Read chunk by chunk, so an oversized body is dropped before it is all in memory.
+        if let Some(len) = res.content_length() {
+            check_response_size(len as usize)?;
+        }
+        let mut bytes = Vec::new();
+        loop {
+            match res.chunk().await {
+                Ok(Some(chunk)) => {
+                    check_response_size(bytes.len() + chunk.len())?;
+                    bytes.extend_from_slice(&chunk);
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    self.breaker.record_failure();
+                    return Err(self.request_error(e));
+                }
+            }
+        }
+        let mut body = String::from_utf8_lossy(&bytes).into_owned();
+
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            self.breaker.record_failure();
+        } else {
+            self.breaker.record_success();
+        }
+
+        if let Some(table) = missing_table(status, &path, &body) {
+            if self.missing_tables.lock().unwrap().insert(table.clone()) {
+                tracing::warn!(
+                    "Supabase table `{}` does not exist, run `ensure-schema --apply` to \
+                     create it; writes to it are skipped until then",
+                    table
+                );
+            }
+            return Err(SupabaseError::MissingTable { table }.into());
+        }
+
+        if !status.is_success() {
+            let content_type = headers.get(CONTENT_TYPE).and_then(|ct| ct.to_str().ok());
+            if let Some(summary) = summarize_error_body(content_type, &body) {
+                if matches!(
+                    status,
+                    StatusCode::BAD_GATEWAY
+                        | StatusCode::SERVICE_UNAVAILABLE
+                        | StatusCode::GATEWAY_TIMEOUT
+                ) {
+                    return Err(SupabaseError::Gateway { status, summary }.into());
+                }
+                body = summary;
+            }
+        }
+
+        Ok((status, headers, body))
+    }
+
+    fn request_error(&self, e: reqwest::Error) -> anyhow::Error {
+        if e.is_timeout() {
+            SupabaseError::Timeout {
+                after: self.timeout,
+            }
+            .into()
+        } else {
+            e.into()
+        }
+    }
+
+    /// Fetches the rows of `T`'s table matching all `filters`, using the read
+    /// key. Only `T::columns()` are requested, so extra columns of a wide table
+    /// aren't transferred.
+    pub async fn select<T: SupabaseModel + DeserializeOwned>(
+        &self,
+        filters: &[(&str, Filter)],
+    ) -> Result<Vec<T>> {
+        let table = T::table_name();
+        let mut query = vec![format!("select={}", T::columns().join(","))];
+        query.extend(filter_query(filters));
+        let endpoint = format!("{}/rest/v1/{}?{}", self.url, table, query.join("&"));
+
+        let request = self.authed_read(self.client.get(&endpoint));
+
+        let (status, body) = self.execute(request).await?;
+
+        if !status.is_success() {
+            anyhow::bail!(
+                "Failed to select from `{}`: {} (status: {})",
+                table,
+                truncate_body(&body),
+                status
+            );
+        }
+
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Counts the rows of `T`'s table matching all `filters`, using the read key.
+    pub async fn count<T: SupabaseModel>(&self, filters: &[(&str, Filter)]) -> Result<u64> {
+        let table = T::table_name();
+        let mut query = vec![format!("select={}", T::primary_key())];
+        query.extend(filter_query(filters));
+        let endpoint = format!("{}/rest/v1/{}?{}", self.url, table, query.join("&"));
+
+        let request = self
+            .authed_read(self.client.head(&endpoint))
+            .header("Prefer", "count=exact");
+
+        let (status, headers, body) = self.execute_with_headers(request).await?;
+
+        if !status.is_success() {
+            anyhow::bail!(
+                "Failed to count rows of `{}`: {} (status: {})",
+                table,
+                truncate_body(&body),
+                status
+            );
+        }
+
+        // `Content-Range: 0-24/3573`, or `*/0` when nothing matched
+        let range = headers
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| anyhow::anyhow!("Count of `{}` returned no Content-Range", table))?;
+        let total = range
+            .rsplit('/')
+            .next()
+            .and_then(|total| total.parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("Unexpected Content-Range `{}`", range))?;
+        Ok(total)
+    }
+
+    /// Inserts `records` in requests of at most `--supabase-batch-size` rows.
+    /// A failed request stops there; the rows of earlier requests stay written.
+    pub async fn insert_many<T: SupabaseModel>(&self, records: &[T]) -> Result<()> {
+        self.insert_many_with(records, self.prefer).await
+    }
+
+    /// Same as `insert_many`, overriding the `return=` preference for this call.
+    pub async fn insert_many_with<T: SupabaseModel>(
+        &self,
+        records: &[T],
+        prefer: ReturnPreference,
+    ) -> Result<()> {
+        let table = T::table_name();
+        let endpoint = format!("{}/rest/v1/{}", self.url, table);
+
+        for (i, chunk) in records.chunks(self.batch_size).enumerate() {
+            let request = self
+                .authed(self.client.post(&endpoint))
+                .header("Prefer", prefer.header_value())
+                .json(chunk);
+
+            let (status, body) = self.execute(request).await?;
+
+            if !status.is_success() {
+                anyhow::bail!(
+                    "Failed to insert records {}..{} into `{}`: {}",
+                    i * self.batch_size,
+                    i * self.batch_size + chunk.len(),
+                    table,
+                    truncate_body(&body)
+                );
+            }
+
+            if body.is_empty() {
+                debug!("[Supabase] Inserted {} rows into `{}`", chunk.len(), table);
+            } else {
+                debug!(
+                    "[Supabase] Inserted {} rows into `{}`: {}",
+                    chunk.len(),
+                    table,
+                    truncate_body(&body)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Generic insert function usable by all Supabase models
+    pub async fn insert<T: SupabaseModel>(&self, record: &T) -> Result<()> {
+        self.insert_with(record, self.prefer).await
+    }
+
+    /// Same as `insert`, overriding the `return=` preference for this call.
+    pub async fn insert_with<T: SupabaseModel>(
+        &self,
+        record: &T,
+        prefer: ReturnPreference,
+    ) -> Result<()> {
+        let table = T::table_name();
+        let endpoint = format!("{}/rest/v1/{}", self.url, table);
+
+        let request = self
+            .authed(self.client.post(&endpoint))
+            .header("Prefer", prefer.header_value())
+            .json(record);
+
+        let (status, body) = self.execute(request).await?;
+
+        if !status.is_success() {
+            anyhow::bail!("Failed to insert record: {}", truncate_body(&body));
+        }
+
+        if body.is_empty() {
+            debug!("[Supabase] Inserted into `{}`", table);
+        } else {
+            debug!(
+                "[Supabase] Inserted into `{}`: {}",
+                table,
+                truncate_body(&body)
+            );
+        }
+        Ok(())
+    }
+
+    /// Inserts `record` and deserializes the representation Supabase returns,
+    /// capturing server-generated columns such as ids and defaults.
+    ///
+    /// PostgREST returns the inserted rows as an array, so `R` is typically a
+    /// `Vec` of a struct with the generated fields.
+    pub async fn insert_returning<T: SupabaseModel, R: DeserializeOwned>(
+        &self,
+        record: &T,
+    ) -> Result<R> {
+        let table = T::table_name();
+        let endpoint = format!("{}/rest/v1/{}", self.url, table);
+
+        let request = self
+            .authed(self.client.post(&endpoint))
+            .header("Prefer", ReturnPreference::Representation.header_value())
+            .json(record);
+
+        let (status, body) = self.execute(request).await?;
+
+        if !status.is_success() {
+            anyhow::bail!("Failed to insert record: {}", truncate_body(&body));
+        }
+
+        debug!("[Supabase] Inserted into `{}`", table);
+        crate::parse::parse_response(&body, false)
+            .map_err(|e| e.context(format!("Failed to parse rows returned by `{}`", table)))
+    }
+
+    /// Inserts `record` unless a row with the same primary key exists, in
+    /// which case nothing happens. Meant for append-only tables.
+    pub async fn insert_ignore_conflicts<T: SupabaseModel>(&self, record: &T) -> Result<()> {
+        let table = T::table_name();
+        let endpoint = format!(
+            "{}/rest/v1/{}?on_conflict={}",
+            self.url,
+            table,
+            T::primary_key()
+        );
+
+        let request = self
+            .authed(self.client.post(&endpoint))
+            .header("Prefer", "resolution=ignore-duplicates,return=minimal")
+            .json(record);
+
+        let (status, body) = self.execute(request).await?;
+
+        if !status.is_success() {
+            anyhow::bail!(
+                "Failed to insert record: {} - {}",
+                status,
+                truncate_body(&body)
+            );
+        }
+
+        debug!(
+            "[Supabase] {} Inserted into `{}` (duplicates ignored)",
+            mark_ok(),
+            table
+        );
+        Ok(())
+    }
+
+    /// Inserts the record, or updates the existing row with the same primary
+    /// key. Columns in `T::immutable_columns()` keep their first-inserted
+    /// value, see [`SupabaseClient::insert_then_update`].
+    pub async fn upsert<T: SupabaseModel>(&self, record: &T) -> Result<()> {
+        self.merge_rows(std::slice::from_ref(record)).await?;
+        debug!(
+            "[Supabase] {} Upserted into `{}`",
+            mark_ok(),
+            T::table_name()
+        );
+        Ok(())
+    }
+
+    /// Upserts many records of the same table in requests of at most
+    /// `--supabase-batch-size` rows, see [`SupabaseClient::upsert`].
+    pub async fn upsert_many<T: SupabaseModel>(&self, records: &[T]) -> Result<()> {
+        self.merge_rows(records).await?;
+        debug!(
+            "[Supabase] {} Upserted {} rows into `{}`",
+            mark_ok(),
+            records.len(),
+            T::table_name()
+        );
+        Ok(())
+    }
+
+    /// Posts `records` with merge-duplicates, in requests of at most
+    /// `--supabase-batch-size` rows. With `--upsert-omit-nulls`, null columns
+    /// are left out and each batch is sent in one request per set of
+    /// remaining columns, since PostgREST needs the same keys on every row of
+    /// a bulk request.
+    async fn merge_rows<T: SupabaseModel>(&self, records: &[T]) -> Result<()> {
+        if !T::immutable_columns().is_empty() {
+            return self.insert_then_update(records).await;
+        }
+        let endpoint = format!("{}/rest/v1/{}", self.url, T::table_name());
+
+        for batch in records.chunks(self.batch_size) {
+            for rows in upsert_groups(batch, self.omit_nulls)? {
+                let request = self
+                    .authed(self.client.post(&endpoint))
+                    .header("Prefer", "resolution=merge-duplicates")
+                    .json(&rows);
+
+                let (status, body) = self.execute(request).await?;
+
+                if !status.is_success() {
+                    anyhow::bail!(
+                        "Failed to upsert into `{}`: {} - {}",
+                        T::table_name(),
+                        status,
+                        truncate_body(&body)
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Upsert for models with immutable columns, which merge-duplicates would
+    /// overwrite: inserts the new rows of each batch with every column, then
+    /// patches the rows that already existed with all but the immutable ones.
+    async fn insert_then_update<T: SupabaseModel>(&self, records: &[T]) -> Result<()> {
+        let table = T::table_name();
+        let pk = T::primary_key();
+        let endpoint = format!(
+            "{}/rest/v1/{}?on_conflict={}&select={}",
+            self.url, table, pk, pk
+        );
+        let mutable: Vec<&str> = T::columns()
+            .iter()
+            .copied()
+            .filter(|column| *column != pk && !T::immutable_columns().contains(column))
+            .collect();
+
+        for batch in records.chunks(self.batch_size) {
+            // Only the rows actually inserted come back, conflicts are skipped.
+            let mut inserted = HashSet::new();
+            for rows in upsert_groups(batch, self.omit_nulls)? {
+                let request = self
+                    .authed(self.client.post(&endpoint))
+                    .header(
+                        "Prefer",
+                        "resolution=ignore-duplicates,return=representation",
+                    )
+                    .json(&rows);
+
+                let (status, body) = self.execute(request).await?;
+
+                if !status.is_success() {
+                    anyhow::bail!(
+                        "Failed to upsert into `{}`: {} - {}",
+                        table,
+                        status,
+                        truncate_body(&body)
+                    );
+                }
+                let returned: Vec<Value> = serde_json::from_str(&body)
+                    .with_context(|| format!("Failed to parse rows inserted into `{}`", table))?;
+                inserted.extend(
+                    returned
+                        .iter()
+                        .filter_map(|row| row.get(pk))
+                        .map(key_string),
+                );
+            }
+
+            for record in batch {
+                if !inserted.contains(&primary_key_value(record)?) {
+                    self.update(record, &mutable).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the write key and JSON content type every write request needs;
+    /// callers only add their operation's `Prefer`.
+    fn authed(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .header("apikey", self.key.expose())
+            .header("Authorization", format!("Bearer {}", self.key.expose()))
+            .header("Content-Type", "application/json")
+    }
+
+    /// Same as [`SupabaseClient::authed`] with the read key, for `select`/`count`.
+    fn authed_read(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .header("apikey", self.read_key.expose())
+            .header(
+                "Authorization",
+                format!("Bearer {}", self.read_key.expose()),
+            )
+            .header("Content-Type", "application/json")
+    }
+
+    /// Fails with [`SupabaseError::MissingColumn`] unless `T`'s table has `column`.
+    async fn ensure_column<T: SupabaseModel>(&self, column: &str) -> Result<()> {
+        let table = T::table_name();
+        let endpoint = format!("{}/rest/v1/{}?select={}&limit=0", self.url, table, column);
+
+        let request = self.authed_read(self.client.get(&endpoint));
+
+        let (status, body) = self.execute(request).await?;
+
+        // 42703 is Postgres' undefined_column
+        if status == StatusCode::BAD_REQUEST && body.contains("42703") {
+            return Err(SupabaseError::MissingColumn {
+                table: table.to_string(),
+                column: column.to_string(),
+            }
+            .into());
+        }
+        if !status.is_success() {
+            anyhow::bail!(
+                "Failed to check column `{}` of `{}`: {} (status: {})",
+                column,
+                table,
+                truncate_body(&body),
+                status
+            );
+        }
+        Ok(())
+    }
+
+    /// Writes only `columns` of `record` to its existing row, found by primary
+    /// key; the other columns are left untouched. With `--upsert-omit-nulls`,
+    /// null fields are skipped as well.
+    ///
+    /// # Errors
+    /// If a column isn't one of `T::columns()`, or the request fails.
+    pub async fn update<T: SupabaseModel>(&self, record: &T, columns: &[&str]) -> Result<()> {
+        let table = T::table_name();
+        let pk = T::primary_key();
+        if let Some(unknown) = columns.iter().find(|c| !T::columns().contains(c)) {
+            anyhow::bail!("`{}` has no column `{}` to update", table, unknown);
+        }
+
+        let key = primary_key_value(record)?;
+        let mut row = serde_json::to_value(record)?;
+        if let Some(fields) = row.as_object_mut() {
+            fields.retain(|column, value| {
+                columns.contains(&column.as_str()) && !(self.omit_nulls && value.is_null())
+            });
+        }
+
+        let request = self
+            .authed(self.client.patch(format!(
+                "{}/rest/v1/{}?{}={}",
+                self.url,
+                table,
+                pk,
+                urlencoding::encode(&format!("eq.{}", key))
+            )))
+            .header("Prefer", "return=minimal")
+            .json(&row);
+
+        let (status, body) = self.execute(request).await?;
+
+        if !status.is_success() {
+            anyhow::bail!(
+                "Failed to update `{}` where {}={}: {} - {}",
+                table,
+                pk,
+                key,
+                status,
+                truncate_body(&body)
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether `T`'s table exists, as seen by PostgREST.
+    pub async fn table_exists<T: SupabaseModel>(&self) -> Result<bool> {
+        let table = T::table_name();
+        let endpoint = format!("{}/rest/v1/{}?limit=0", self.url, table);
+
+        let request = self.authed_read(self.client.get(&endpoint));
+
+        let (status, body) = match self.execute(request).await {
+            Ok(response) => response,
+            Err(e) if SupabaseError::is_missing_table(&e) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        if !status.is_success() {
+            anyhow::bail!(
+                "Failed to check table `{}`: {} (status: {})",
+                table,
+                truncate_body(&body),
+                status
+            );
+        }
+        Ok(true)
+    }
+
+    /// Calls the Postgres function `function` with the named `args`, using
+    /// the write key.
+    pub async fn rpc(&self, function: &str, args: &Value) -> Result<()> {
+        let endpoint = format!("{}/rest/v1/rpc/{}", self.url, function);
+
+        let request = self.authed(self.client.post(&endpoint)).json(args);
+
+        let (status, body) = self.execute(request).await?;
+
+        // PGRST202 is PostgREST's "function not found"
+        if body.contains("PGRST202") {
+            anyhow::bail!(
+                "Supabase has no function `{}` taking {}, create it first",
+                function,
+                args
+            );
+        }
+        if !status.is_success() {
+            anyhow::bail!(
+                "Failed to call `{}`: {} (status: {})",
+                function,
+                truncate_body(&body),
+                status
+            );
+        }
+        Ok(())
+    }
+
+    /// Deletes every row of `T`'s table.
+    ///
+    /// Matches rows on a non-null primary key, which every row has, after
+    /// checking that the column exists: a misconfigured key fails loudly
+    /// instead of deleting nothing or only part of the table.
+    pub async fn delete_all<T: SupabaseModel>(&self) -> Result<&Self> {
+        let table = T::table_name();
+        let pk = T::primary_key();
+        self.ensure_column::<T>(pk)
+            .await
+            .with_context(|| format!("Refusing to delete all rows of `{}`", table))?;
+        let endpoint = format!("{}/rest/v1/{}?{}=not.is.null", self.url, table, pk);
+
+        let request = self
+            .authed(self.client.delete(&endpoint))
+            .header("Prefer", "return=minimal");
+
+        let (status, body) = self.execute(request).await?;
+
+        if !status.is_success() {
+            anyhow::bail!(
+                "Failed to delete table `{}`: {}",
+                table,
+                truncate_body(&body)
+            );
+        }
+
+        debug!("[Supabase] Deleted all rows from `{}`", table);
+        Ok(self)
+    }
+
+    /// Deletes the rows matching all `filters`.
+    ///
+    /// Refuses to run without filters, use `delete_all` to clear a table.
+    pub async fn delete_where<T: SupabaseModel>(
+        &self,
+        filters: &[(&str, Filter)],
+    ) -> Result<&Self> {
+        let table = T::table_name();
+        if filters.is_empty() {
+            anyhow::bail!("Refusing to delete from `{}` without a filter", table);
+        }
+
+        let query = filter_query(filters);
+        let endpoint = format!("{}/rest/v1/{}?{}", self.url, table, query.join("&"));
+
+        let request = self
+            .authed(self.client.delete(&endpoint))
+            .header("Prefer", "return=minimal");
+
+        let (status, body) = self.execute(request).await?;
+
+        if !status.is_success() {
+            anyhow::bail!(
+                "Failed to delete from `{}` where {}: {} (status: {})",
+                table,
+                query.join(" and "),
+                truncate_body(&body),
+                status
+            );
+        }
+
+        debug!(
+            "[Supabase] {} Deleted from `{}` where {}",
+            mark_ok(),
+            table,
+            query.join(" and ")
+        );
+        Ok(self)
+    }
+
+    pub async fn delete_one<T: SupabaseModel>(&self, primary_key_value: &str) -> Result<&Self> {
+        let table = T::table_name();
+        let pk = T::primary_key();
+
+        let endpoint = format!(
+            "{}/rest/v1/{}?{}={}",
+            self.url,
+            table,
+            pk,
+            urlencoding::encode(&format!("eq.{}", primary_key_value))
+        );
+
+        let request = self
+            .authed(self.client.delete(&endpoint))
+            .header("Prefer", "return=minimal"); // Supabase standard
+
+        let (status, body) = self.execute(request).await?;
+
+        if !status.is_success() {
+            anyhow::bail!(
+                "Failed to delete from `{}` where {}={}: {} (status: {})",
+                table,
+                pk,
+                primary_key_value,
+                truncate_body(&body),
+                status
+            );
+        }
+
+        debug!(
+            "[Supabase] {} Deleted from `{}` where {}={}",
+            mark_ok(),
+            table,
+            pk,
+            primary_key_value
+        );
+
+        Ok(self)
+    }
+}
+
+/// The value of `T::primary_key()` in `record`, as a string.
+pub(crate) fn primary_key_value<T: SupabaseModel>(record: &T) -> Result<String> {
+    let value = serde_json::to_value(record)?;
+    let key = value.get(T::primary_key()).ok_or_else(|| {
+        anyhow::anyhow!(
+            "`{}` record has no `{}` field",
+            T::table_name(),
+            T::primary_key()
+        )
+    })?;
+    Ok(key_string(key))
+}
+
+/// A primary key value as used in filters: strings unquoted.
+fn key_string(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
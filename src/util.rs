@@ -0,0 +1,147 @@
+//! Small helpers shared across modules.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Default of `--backoff-jitter`
+pub const DEFAULT_JITTER: f64 = 0.2;
+
+/// Jitter fraction of every [`Backoff`], set once at startup from
+/// `--backoff-jitter`; stored as the bits of an `f64`
+static JITTER: AtomicU64 = AtomicU64::new(DEFAULT_JITTER.to_bits());
+
+pub fn set_backoff_jitter(fraction: f64) {
+    JITTER.store(fraction.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+}
+
+fn backoff_jitter() -> f64 {
+    f64::from_bits(JITTER.load(Ordering::Relaxed))
+}
+
+/// Exponential backoff with jitter, so instances retrying after the same
+/// outage spread out instead of hitting the service all at once.
+///
+/// The delay before retry `n` (counted from 0) is `base * multiplier^n`,
+/// capped at `max`, then scaled by a random factor within `1 ± jitter`.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    pub base: Duration,
+    pub max: Duration,
+    pub multiplier: f64,
+    /// Fraction of the delay that is randomized, between 0 and 1
+    pub jitter: f64,
+}
+
+impl Backoff {
+    /// Doubles from `base` up to `max`, with the `--backoff-jitter` fraction.
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            multiplier: 2.0,
+            jitter: backoff_jitter(),
+        }
+    }
+
+    /// The delay before retry `attempt`, without jitter.
+    pub fn nominal(&self, attempt: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(attempt.min(i32::MAX as u32) as i32);
+        let secs = self.base.as_secs_f64() * factor;
+        // Also catches an infinite product.
+        if secs >= self.max.as_secs_f64() {
+            return self.max;
+        }
+        Duration::try_from_secs_f64(secs).unwrap_or(self.max)
+    }
+
+    /// The delay before retry `attempt`, jittered.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.jittered(attempt, random_unit())
+    }
+
+    /// [`Backoff::nominal`] scaled by `1 - jitter + 2 * jitter * unit`, for a
+    /// `unit` in `[0, 1)`: within `nominal * (1 ± jitter)`, saturating at
+    /// [`Duration::MAX`].
+    pub fn jittered(&self, attempt: u32, unit: f64) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 - jitter + 2.0 * jitter * unit.clamp(0.0, 1.0);
+        Duration::try_from_secs_f64(self.nominal(attempt).as_secs_f64() * factor)
+            .unwrap_or(Duration::MAX)
+    }
+}
+
+/// A random number in `[0, 1)`, from the randomly keyed std hasher; good
+/// enough to spread retries, not for anything security related.
+fn random_unit() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backoff(max: Duration, jitter: f64) -> Backoff {
+        Backoff {
+            base: Duration::from_millis(100),
+            max,
+            multiplier: 2.0,
+            jitter,
+        }
+    }
+
+    #[test]
+    fn delays_grow_by_the_multiplier() {
+        let backoff = backoff(Duration::from_secs(60), 0.0);
+        let delays: Vec<Duration> = (0..4).map(|attempt| backoff.nominal(attempt)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    #[test]
+    fn delays_are_capped_at_max() {
+        let backoff = backoff(Duration::from_secs(1), 0.0);
+        assert_eq!(backoff.nominal(4), Duration::from_secs(1));
+        assert_eq!(backoff.nominal(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn unbounded_max_survives_huge_attempts() {
+        // As used by `submit_with_retry`, whose attempts are bounded instead.
+        let backoff = backoff(Duration::MAX, 0.0);
+        assert_eq!(backoff.nominal(3), Duration::from_millis(800));
+        assert_eq!(backoff.nominal(u32::MAX), Duration::MAX);
+        assert_eq!(backoff.jittered(u32::MAX, 0.5), Duration::MAX);
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let backoff = backoff(Duration::from_secs(60), 0.2);
+        for attempt in 0..5 {
+            let nominal = backoff.nominal(attempt);
+            let low = backoff.jittered(attempt, 0.0);
+            let high = backoff.jittered(attempt, 1.0 - f64::EPSILON);
+            assert_eq!(low, nominal.mul_f64(0.8));
+            assert!(high <= nominal.mul_f64(1.2));
+            assert!(high > nominal);
+            assert_eq!(backoff.jittered(attempt, 0.5), nominal);
+        }
+    }
+
+    #[test]
+    fn random_delays_stay_within_bounds() {
+        let backoff = backoff(Duration::from_secs(60), 0.2);
+        for _ in 0..100 {
+            let delay = backoff.delay(2);
+            assert!(delay >= Duration::from_millis(320));
+            assert!(delay <= Duration::from_millis(480));
+        }
+    }
+}
@@ -9,20 +9,20 @@ use linera_client::config::GenesisConfig;
 use linera_core::wallet;
 use linera_faucet_client::Faucet;
 use linera_persistent::{self as persistent, Persist};
-use linera_views::{
-    lru_prefix_cache::StorageCacheConfig,
-    rocks_db::{PathWithGuard, RocksDbSpawnMode, RocksDbStoreConfig, RocksDbStoreInternalConfig},
-};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use crate::storage::Storage;
+use crate::logging::{mark_ok, short_owner};
+use crate::storage::{self, Storage, StorageConfig};
 
 #[derive(Clone)]
 pub struct PersistentWallet {
     pub(crate) wallet: Wallet,
     storage: Storage,
     pub signer: InMemorySigner,
+    /// Where the keystore is saved when a key is added
+    keystore_path: PathBuf,
 }
 
 /// A wallet that stores the user's chains and keys in memory.
@@ -33,25 +33,122 @@ pub struct Wallet {
     pub(crate) genesis_config: GenesisConfig,
 }
 
+/// Where the wallet, including the genesis config, is cached between runs
+const WALLET_PATH: &str = "wallet.json";
+
 // for local testing
-const FAUCET_URL: &str = "http://localhost:8079";
+pub(crate) const FAUCET_URL: &str = "http://localhost:8079";
 // const FAUCET_URL: &str = "https://faucet.testnet-conway.linera.net/";
 
-impl PersistentWallet {
-    pub fn signer_address(&self) -> AccountOwner {
-        self.signer.keys()[0].0
+/// Faucets used when no cached wallet can be reused, exposed as CLI flags
+#[derive(clap::Args, Clone, Debug)]
+pub struct FaucetConfig {
+    /// Faucet serving the genesis config and claiming chains; repeat to fall back on the next one in order
+    #[arg(
+        long = "faucet-url",
+        value_name = "URL",
+        default_value = FAUCET_URL,
+        global = true
+    )]
+    pub faucet_urls: Vec<String>,
+
+    /// Time each faucet gets before moving on to the next
+    #[arg(
+        long = "faucet-timeout",
+        value_name = "SECS",
+        default_value_t = 30,
+        global = true
+    )]
+    pub faucet_timeout_secs: u64,
+}
+
+impl Default for FaucetConfig {
+    fn default() -> Self {
+        Self {
+            faucet_urls: vec![FAUCET_URL.to_string()],
+            faucet_timeout_secs: 30,
+        }
     }
+}
 
-    pub fn create_keystore(
-        keystore_path: PathBuf,
-    ) -> Result<persistent::File<InMemorySigner>, anyhow::Error> {
-        if keystore_path.exists() {
-            println!("Keystore exists: {}", keystore_path.display());
+impl FaucetConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.faucet_timeout_secs)
+    }
+}
+
+/// Fetches the genesis config from `url`, within `timeout`.
+pub(crate) async fn fetch_genesis_config(
+    url: &str,
+    timeout: Duration,
+) -> Result<GenesisConfig, anyhow::Error> {
+    tokio::time::timeout(timeout, Faucet::new(url.to_string()).genesis_config())
+        .await
+        .map_err(|_| anyhow::anyhow!("faucet {} timed out after {:?}", url, timeout))?
+        .map_err(Into::into)
+}
+
+impl Wallet {
+    /// The owner of the default chain.
+    fn default_owner(&self) -> Option<AccountOwner> {
+        self.default
+            .and_then(|chain_id| self.chains.get(chain_id))
+            .and_then(|chain| chain.owner)
+    }
+
+    /// Reads the wallet at `path` if its default chain is owned by one of `owners`.
+    fn read_cached(path: &Path, owners: &[AccountOwner]) -> Option<Self> {
+        if !path.exists() {
+            return None;
         }
-        Ok(persistent::File::read(&keystore_path)?)
+        let wallet = match persistent::File::<Wallet>::read(path) {
+            Ok(file) => file.into_value(),
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable wallet {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let owns_default = wallet
+            .default_owner()
+            .is_some_and(|owner| owners.contains(&owner));
+        if !owns_default {
+            tracing::warn!(
+                "Wallet {} has no default chain owned by the keystore, claiming a new one",
+                path.display()
+            );
+            return None;
+        }
+        Some(wallet)
+    }
+
+    /// Claims a chain for `owner` from the first faucet that succeeds, then
+    /// warns if any other faucet serves a different genesis config.
+    async fn claim(owner: AccountOwner, faucets: &FaucetConfig) -> Result<Self, anyhow::Error> {
+        let timeout = faucets.timeout();
+        let mut errors = Vec::new();
+        for url in &faucets.faucet_urls {
+            match tokio::time::timeout(timeout, Self::claim_from(url, owner)).await {
+                Ok(Ok(wallet)) => {
+                    println!("{} Claimed chain from faucet {}", mark_ok(), url);
+                    check_genesis_consistency(&wallet.genesis_config, url, faucets).await;
+                    return Ok(wallet);
+                }
+                Ok(Err(e)) => {
+                    tracing::warn!("Faucet {} failed: {:#}", url, e);
+                    errors.push(format!("{}: {:#}", url, e));
+                }
+                Err(_) => {
+                    tracing::warn!("Faucet {} timed out after {:?}", url, timeout);
+                    errors.push(format!("{}: timed out", url));
+                }
+            }
+        }
+        anyhow::bail!("No faucet could claim a chain ({})", errors.join("; "))
     }
-    pub async fn new(keystore_path: Option<PathBuf>) -> Result<Self, anyhow::Error> {
-        let faucet = Faucet::new(FAUCET_URL.to_string());
+
+    /// Fetches the genesis config from the faucet at `url` and claims a chain for `owner`.
+    async fn claim_from(url: &str, owner: AccountOwner) -> Result<Self, anyhow::Error> {
+        let faucet = Faucet::new(url.to_string());
 
         let mut wallet = Wallet {
             chains: wallet::Memory::default(),
@@ -59,18 +156,6 @@ impl PersistentWallet {
             genesis_config: faucet.genesis_config().await?,
         };
 
-        let (signer, owner) = if let Some(keystore_path) = keystore_path {
-            let signer = Self::create_keystore(keystore_path)?;
-            let owner = signer.keys()[0].0;
-            (signer, owner)
-        } else {
-            let mut signer = InMemorySigner::new(None);
-            signer.generate_new();
-            let signer = persistent::File::new(Path::new("keystore.json"), signer.clone())?;
-            let owner = signer.keys()[0].0;
-            (signer, owner)
-        };
-
         let description = faucet.claim(&owner).await?;
 
         let chain_id = description.id();
@@ -81,45 +166,137 @@ impl PersistentWallet {
                 ..description.into()
             },
         );
+        wallet.default = Some(chain_id);
+
+        Ok(wallet)
+    }
+}
 
-        if wallet.default.is_none() {
-            wallet.default = Some(chain_id);
+/// Warns about every faucet other than `accepted_url` whose genesis config
+/// differs from `accepted`: falling back on it later would mean another network.
+async fn check_genesis_consistency(
+    accepted: &GenesisConfig,
+    accepted_url: &str,
+    faucets: &FaucetConfig,
+) {
+    let Ok(accepted) = serde_json::to_value(accepted) else {
+        return;
+    };
+    for url in faucets
+        .faucet_urls
+        .iter()
+        .filter(|url| *url != accepted_url)
+    {
+        match fetch_genesis_config(url, faucets.timeout()).await {
+            Ok(other) if serde_json::to_value(&other).ok().as_ref() == Some(&accepted) => {}
+            Ok(_) => tracing::warn!("Faucet {} serves a different genesis config than {}, it belongs to another network", url, accepted_url),
+            Err(e) => tracing::debug!("Couldn't compare the genesis config of {}: {:#}", url, e),
         }
+    }
+}
 
-        let inner_config = RocksDbStoreInternalConfig {
-            path_with_guard: PathWithGuard::new("./client.db".into()),
-            spawn_mode: RocksDbSpawnMode::SpawnBlocking, // Best for tokio multi-threaded
-            max_stream_queries: 20,                      // Higher for better concurrency
-        };
+impl PersistentWallet {
+    /// The key signing for the default chain: its owner in the wallet if the
+    /// keystore holds that key, which `keys rotate --make-default` changes, or
+    /// else the first key.
+    pub fn signer_address(&self) -> AccountOwner {
+        let keys = self.signer.keys();
+        self.wallet
+            .default_owner()
+            .filter(|owner| keys.iter().any(|(key, _)| key == owner))
+            .unwrap_or(keys[0].0)
+    }
 
-        let config = RocksDbStoreConfig {
-            inner_config,
-            storage_cache_config: StorageCacheConfig {
-                max_cache_size: 100000,
-                max_cache_entries: 100000,
-                max_cache_find_key_values_size: 100000,
-                max_cache_find_keys_size: 100000,
-                max_cache_value_size: 100000,
-                max_find_key_values_entry_size: 100000,
-                max_find_keys_entry_size: 100000,
-                max_value_entry_size: 100000,
-            },
+    /// Generates a new key and saves the keystore, with the existing keys, so
+    /// the key is on disk before anything depends on it.
+    ///
+    /// # Errors
+    /// If the keystore can't be written.
+    pub fn generate_key(&mut self) -> Result<AccountOwner, anyhow::Error> {
+        let owner = AccountOwner::from(self.signer.generate_new());
+        persistent::File::new(&self.keystore_path, self.signer.clone())?;
+        Ok(owner)
+    }
+
+    /// Makes `owner` the owner of the default chain in `wallet.json`, so the
+    /// next start signs with its key.
+    ///
+    /// # Errors
+    /// If there is no default chain, the keystore doesn't hold the key of
+    /// `owner`, or the wallet can't be written.
+    pub fn set_default_owner(&mut self, owner: AccountOwner) -> Result<(), anyhow::Error> {
+        if !self.signer.keys().iter().any(|(key, _)| *key == owner) {
+            anyhow::bail!("the keystore has no key for {}", short_owner(&owner));
+        }
+        let chain_id = self
+            .wallet
+            .default
+            .ok_or_else(|| anyhow::anyhow!("the wallet has no default chain"))?;
+        let mut chain =
+            self.wallet.chains.get(chain_id).ok_or_else(|| {
+                anyhow::anyhow!("default chain {} is not in the wallet", chain_id)
+            })?;
+        chain.owner = Some(owner);
+        self.wallet.chains.insert(chain_id, chain);
+        persistent::File::new(Path::new(WALLET_PATH), self.wallet.clone())?;
+        Ok(())
+    }
+
+    pub fn create_keystore(
+        keystore_path: PathBuf,
+    ) -> Result<persistent::File<InMemorySigner>, anyhow::Error> {
+        if keystore_path.exists() {
+            println!("Keystore exists: {}", keystore_path.display());
+        }
+        Ok(persistent::File::read(&keystore_path)?)
+    }
+
+    /// Loads the wallet, reusing `wallet.json` when it already holds a default
+    /// chain owned by the keystore. Only a missing or foreign wallet contacts the
+    /// faucets, to fetch the genesis config and claim a new chain; the result,
+    /// genesis config included, is cached in `wallet.json` for the next start.
+    pub async fn new(
+        keystore_path: Option<PathBuf>,
+        storage_config: &StorageConfig,
+        faucets: &FaucetConfig,
+    ) -> Result<Self, anyhow::Error> {
+        let (signer, keystore_path) = if let Some(keystore_path) = keystore_path {
+            (Self::create_keystore(keystore_path.clone())?, keystore_path)
+        } else {
+            let keystore_path = PathBuf::from("keystore.json");
+            let mut signer = InMemorySigner::new(None);
+            signer.generate_new();
+            let signer = persistent::File::new(&keystore_path, signer.clone())?;
+            (signer, keystore_path)
         };
+        // After a key rotation the keystore holds several keys, any of which
+        // may own the cached default chain.
+        let owners: Vec<AccountOwner> = signer.keys().into_iter().map(|(owner, _)| owner).collect();
+        let owner = owners[0];
 
-        let storage = linera_storage::DbStorage::maybe_create_and_connect(
-            &config,
-            "linera",
-            Some(linera_execution::WasmRuntime::Wasmer),
-        )
-        .await
-        .expect("failed to create storage");
+        let wallet = match Wallet::read_cached(Path::new(WALLET_PATH), &owners) {
+            Some(wallet) => {
+                println!(
+                    "{} Using cached wallet {}, skipping the faucet",
+                    mark_ok(),
+                    WALLET_PATH
+                );
+                wallet
+            }
+            None => {
+                let wallet = Wallet::claim(owner, faucets).await?;
+                persistent::File::new(Path::new(WALLET_PATH), wallet.clone())?;
+                wallet
+            }
+        };
 
-        persistent::File::new(Path::new("wallet.json"), wallet.clone())?;
+        let storage = storage::connect(storage_config).await?;
 
         Ok(PersistentWallet {
             wallet,
             signer: signer.into_value(),
             storage,
+            keystore_path,
         })
     }
 
@@ -0,0 +1,716 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use linera_base::data_types::BlockHeight;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Mutex, MutexGuard};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::chain::{Application, QueryExecutor};
+use crate::checkpoint::{Checkpoints, CHECKPOINT_PATH};
+use crate::client::ClientEvent;
+use crate::health;
+use crate::metrics::METRICS;
+use crate::models::tournament::{Micros, TournamentDB, TournamentParticipantDB};
+use crate::models::GameCount;
+use crate::parse::parse_response;
+use crate::supabase::buffer::WriteBuffer;
+use crate::supabase::{SupabaseClient, SupabaseError};
+
+pub mod targets;
+
+/// A Supabase table kept in sync by the watcher
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum Table {
+    Tournaments,
+    Participants,
+    Leaderboard,
+    Count,
+    Matches,
+    /// Decoded Swiss/SingleElim brackets, opt-in since older applications lack the query
+    Brackets,
+}
+
+impl Table {
+    /// Tables synced when `--tables` isn't given
+    pub const DEFAULT: [Table; 5] = [
+        Table::Tournaments,
+        Table::Participants,
+        Table::Leaderboard,
+        Table::Count,
+        Table::Matches,
+    ];
+}
+
+/// Where the watcher sends the changes it finds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Output {
+    /// Write changes to Supabase
+    #[default]
+    Supabase,
+    /// Print changes as JSON lines, without touching Supabase
+    Stdout,
+    /// Print changes and write them to Supabase
+    Both,
+}
+
+impl Output {
+    /// Whether Supabase is written, and so must be configured
+    pub fn to_supabase(self) -> bool {
+        matches!(self, Output::Supabase | Output::Both)
+    }
+
+    pub fn to_stdout(self) -> bool {
+        matches!(self, Output::Stdout | Output::Both)
+    }
+}
+
+/// What a sync does when a Supabase write fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Log the failure and carry on; the next sync retries what changed
+    #[default]
+    KeepGoing,
+    /// Stop the watcher on the first failure and exit with an error
+    FailFast,
+}
+
+impl std::fmt::Display for WritePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WritePolicy::KeepGoing => write!(f, "keep-going"),
+            WritePolicy::FailFast => write!(f, "fail-fast"),
+        }
+    }
+}
+
+/// Settings for the `watch` command
+#[derive(Clone, Debug)]
+pub struct WatchConfig {
+    /// Tables to query and write; the others are skipped entirely
+    pub tables: Vec<Table>,
+    /// Reject responses containing fields the response structs don't declare
+    pub strict_schema: bool,
+    /// When set, upserts are buffered and flushed on this interval
+    pub flush_interval: Option<Duration>,
+    /// Buffered rows per table that trigger an early flush
+    pub flush_threshold: usize,
+    /// Overrides the saved checkpoint, so blocks from this height on are reprocessed
+    pub from_block: Option<BlockHeight>,
+    /// Time a sync cycle may spend before deferring the rest to the next cycle
+    pub cycle_budget: Option<Duration>,
+    pub output: Output,
+    /// Fetch participants in pages of this size; `None` fetches them in one query
+    pub participants_page_size: Option<usize>,
+    /// Number of recent match `blobHash`es remembered to skip re-inserting them
+    pub match_dedup_window: usize,
+    /// Only sync tournaments starting at or after this time
+    pub tournaments_since: Option<Micros>,
+    /// Pass `tournaments_since` to the service instead of fetching everything
+    pub tournaments_since_server_side: bool,
+    /// Delete all tournaments once the service confirms it has none left
+    pub prune_tournaments: bool,
+    /// Tournaments, with their participants and brackets, synced concurrently
+    pub tournament_concurrency: usize,
+    /// Leave out tournaments failing [`Tournament::check_prize`](crate::models::tournament::Tournament::check_prize)
+    /// instead of writing them with a warning
+    pub skip_inconsistent_prizes: bool,
+    pub write_policy: WritePolicy,
+    /// Cancelled on shutdown; the buffer flushers then flush once more and stop
+    pub shutdown: CancellationToken,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            tables: Table::DEFAULT.to_vec(),
+            strict_schema: false,
+            flush_interval: None,
+            flush_threshold: 100,
+            from_block: None,
+            cycle_budget: None,
+            output: Output::default(),
+            participants_page_size: None,
+            match_dedup_window: 64,
+            tournaments_since: None,
+            tournaments_since_server_side: false,
+            prune_tournaments: false,
+            tournament_concurrency: 4,
+            skip_inconsistent_prizes: false,
+            write_policy: WritePolicy::default(),
+            shutdown: CancellationToken::new(),
+        }
+    }
+}
+
+/// Per-table write buffers used when a flush interval is configured
+pub struct WriteBuffers {
+    pub tournaments: Arc<WriteBuffer<TournamentDB>>,
+    pub participants: Arc<WriteBuffer<TournamentParticipantDB>>,
+    pub count: Arc<WriteBuffer<GameCount>>,
+}
+
+/// What a [`SyncTarget`] needs from the watcher to diff and persist a response
+pub struct SyncContext<'a> {
+    pub app: &'a dyn QueryExecutor,
+    /// Unset when changes only go to stdout
+    pub supabase: Option<&'a SupabaseClient>,
+    /// Set when upserts are buffered instead of written immediately
+    pub buffers: Option<&'a WriteBuffers>,
+    pub strict_schema: bool,
+    /// Print every change as a JSON line
+    pub print: bool,
+    /// When the current cycle has to stop starting new work
+    pub deadline: Option<Instant>,
+    pub write_policy: WritePolicy,
+}
+
+impl SyncContext<'_> {
+    /// Prints a change to `table` as a JSON line when stdout output is on.
+    /// Targets call this right before the matching Supabase write.
+    pub fn print<T: Serialize + ?Sized>(&self, table: &str, op: &str, data: &T) {
+        if self.print {
+            let line = serde_json::json!({ "table": table, "op": op, "data": data });
+            println!("{}", line);
+        }
+    }
+    /// Whether the cycle ran out of time. Targets check this between
+    /// independent writes and return [`BudgetExhausted`] to defer the rest.
+    pub fn budget_exhausted(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Handles a failed write according to the [`WritePolicy`]: logged and
+    /// swallowed when keeping going, returned to stop the cycle otherwise.
+    pub fn write_failed(&self, error: anyhow::Error) -> Result<()> {
+        match self.write_policy {
+            WritePolicy::KeepGoing => {
+                error!("{:#}", error);
+                Ok(())
+            }
+            WritePolicy::FailFast => Err(error),
+        }
+    }
+
+    /// Parses a GraphQL response, honouring `--strict-schema`.
+    pub fn parse<T: DeserializeOwned>(&self, response: &str, what: &str) -> Result<T> {
+        parse_response(response, self.strict_schema)
+            .with_context(|| format!("Failed to parse {}", what))
+    }
+}
+
+/// A sync cycle stopped early because its time budget ran out; the work it
+/// skipped is picked up by the next cycle.
+#[derive(Debug)]
+pub struct BudgetExhausted;
+
+impl std::fmt::Display for BudgetExhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sync budget exhausted, deferring to the next cycle")
+    }
+}
+
+impl std::error::Error for BudgetExhausted {}
+
+/// One unit of the sync loop: a query sent to the application and the
+/// Supabase writes derived from its response.
+///
+/// Implementors keep whatever they last wrote so that `apply` only persists
+/// what changed. Adding a synced table means implementing this trait and
+/// registering the target in [`targets::registry`].
+#[async_trait]
+pub trait SyncTarget: Send + Sync {
+    /// Name used in log lines and errors
+    fn name(&self) -> &'static str;
+
+    /// GraphQL request body sent to the application. The queries of all
+    /// targets are merged into one request when they allow it, see
+    /// [`batch`](crate::chain::batch).
+    fn query(&self) -> String;
+
+    /// Parses `response`, diffs it against the last synced state and
+    /// persists the changes.
+    async fn apply(&self, response: &str, ctx: &SyncContext<'_>) -> Result<()>;
+}
+
+/// Runs one sync cycle at a time. A cycle requested while one runs makes
+/// that cycle run once more when it finishes; requests arriving meanwhile
+/// are coalesced into that single rerun, so cycles never overlap or pile up.
+#[derive(Default)]
+struct CycleGate {
+    /// Held by the running cycle
+    lock: Mutex<()>,
+    /// A cycle was requested, picked up by the running one if there is one
+    pending: AtomicBool,
+    /// The pending cycle is a reconcile, which runs even without new blocks
+    reconcile_pending: AtomicBool,
+}
+
+impl CycleGate {
+    /// Runs `cycle` unless one is already running, which then reruns
+    /// instead. `cycle` gets whether any of the requests it serves was forced.
+    async fn run<F, Fut>(&self, force: bool, mut cycle: F)
+    where
+        F: FnMut(bool) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        if force {
+            self.reconcile_pending.store(true, Ordering::SeqCst);
+        }
+        self.pending.store(true, Ordering::SeqCst);
+        loop {
+            let Ok(guard) = self.lock.try_lock() else {
+                return;
+            };
+            while self.pending.swap(false, Ordering::SeqCst) {
+                let force = self.reconcile_pending.swap(false, Ordering::SeqCst);
+                cycle(force).await;
+            }
+            drop(guard);
+            // A request that came in after the last check but failed to take
+            // the lock would otherwise be lost.
+            if !self.pending.load(Ordering::SeqCst) {
+                return;
+            }
+        }
+    }
+
+    /// Waits for the running cycle, if any; no cycle starts while the guard is held.
+    async fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().await
+    }
+}
+
+/// Mirrors an application's state into Supabase, writing only what changed.
+pub struct Watcher {
+    app: Application,
+    supabase: Option<Arc<SupabaseClient>>,
+    buffers: Option<WriteBuffers>,
+    targets: Vec<Box<dyn SyncTarget>>,
+    strict_schema: bool,
+    cycle_budget: Option<Duration>,
+    print: bool,
+    /// Only kept when writing to Supabase: an inspection run to stdout must
+    /// not make a later run skip blocks it never wrote
+    checkpoints: Option<Checkpoints>,
+    /// Keeps sync cycles from overlapping
+    cycles: CycleGate,
+    /// Set by [`Watcher::drain`], or when a write failed under [`WritePolicy::FailFast`]
+    closed: AtomicBool,
+    write_policy: WritePolicy,
+    /// Cancelled once the watcher stopped on a failed write
+    halted: CancellationToken,
+    halt_error: std::sync::Mutex<Option<anyhow::Error>>,
+}
+
+impl Watcher {
+    /// `supabase` is required unless `config.output` is [`Output::Stdout`],
+    /// in which case it is ignored.
+    pub fn new(
+        app: Application,
+        supabase: Option<Arc<SupabaseClient>>,
+        config: WatchConfig,
+    ) -> Arc<Self> {
+        let supabase = supabase.filter(|_| config.output.to_supabase());
+        let buffers = config
+            .flush_interval
+            .zip(supabase.as_ref())
+            .map(|(interval, supabase)| {
+                let buffers = WriteBuffers {
+                    tournaments: WriteBuffer::new(Arc::clone(supabase), config.flush_threshold),
+                    participants: WriteBuffer::new(Arc::clone(supabase), config.flush_threshold),
+                    count: WriteBuffer::new(Arc::clone(supabase), config.flush_threshold),
+                };
+                let shutdown = &config.shutdown;
+                buffers
+                    .tournaments
+                    .spawn_flusher(interval, shutdown.clone());
+                buffers
+                    .participants
+                    .spawn_flusher(interval, shutdown.clone());
+                buffers.count.spawn_flusher(interval, shutdown.clone());
+                buffers
+            });
+
+        let checkpoints = supabase.as_ref().map(|_| {
+            let checkpoints = Checkpoints::load(CHECKPOINT_PATH);
+            if let Some(height) = config.from_block {
+                if let Err(e) = checkpoints.set(app.chain_id(), height) {
+                    error!("{:#}", e);
+                }
+            }
+            checkpoints
+        });
+
+        Arc::new(Self {
+            app,
+            supabase,
+            buffers,
+            targets: targets::registry(&config),
+            strict_schema: config.strict_schema,
+            cycle_budget: config.cycle_budget,
+            print: config.output.to_stdout(),
+            checkpoints,
+            cycles: CycleGate::default(),
+            closed: AtomicBool::new(false),
+            write_policy: config.write_policy,
+            halted: CancellationToken::new(),
+            halt_error: std::sync::Mutex::new(None),
+        })
+    }
+
+    /// Re-syncs whenever `process_inbox` applies messages addressed to the watched application.
+    pub fn react_to_events(self: &Arc<Self>, mut events: broadcast::Receiver<ClientEvent>) {
+        let this = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(ClientEvent::InboxProcessed { chain_id, messages }) => {
+                        let for_app = messages
+                            .iter()
+                            .filter(|m| m.application_id == Some(this.app.id))
+                            .count();
+                        info!(
+                            "Inbox processed on {}: {} message(s), {} for the watched application",
+                            chain_id,
+                            messages.len(),
+                            for_app
+                        );
+                        if for_app > 0 {
+                            this.sync().await;
+                        }
+                    }
+                    Ok(ClientEvent::CommitteeChanged {
+                        chain_id,
+                        previous,
+                        current,
+                    }) => {
+                        info!(
+                            "Committee changed on {}: epoch {} -> {}",
+                            chain_id, previous, current
+                        );
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Missed {} client event(s)", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Runs every registered [`SyncTarget`] once, writing the changed rows.
+    ///
+    /// Skipped when the chain hasn't advanced past its checkpoint; the
+    /// checkpoint moves forward only when every target synced successfully.
+    /// Once the cycle budget is spent, the remaining targets are deferred to
+    /// the next cycle so a partial outage can't starve later notifications.
+    ///
+    /// Called while a sync runs, this returns at once and the running sync
+    /// goes around once more instead.
+    pub async fn sync(&self) {
+        self.run_targets(false).await
+    }
+
+    /// Same as [`Watcher::sync`], but runs even if the chain didn't advance,
+    /// to catch changes whose notification was missed. Target caches are
+    /// shared, so only genuine diffs are written.
+    pub async fn reconcile(&self) {
+        info!("Reconciling all tables");
+        self.run_targets(true).await
+    }
+
+    /// Runs [`Watcher::reconcile`] every `interval` until the process exits.
+    pub fn spawn_reconciler(self: &Arc<Self>, interval: Duration) {
+        let this = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // The first tick completes immediately; the notification path covers startup.
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                this.reconcile().await;
+            }
+        });
+    }
+
+    /// Runs a sync cycle unless one is already running, see [`CycleGate`].
+    async fn run_targets(&self, force: bool) {
+        self.cycles.run(force, |force| self.run_cycle(force)).await
+    }
+
+    async fn run_cycle(&self, force: bool) {
+        if self.closed.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut failed = false;
+
+        let chain_id = self.app.chain_id();
+        let height = match self.app.next_block_height().await {
+            Ok(height) => Some(height),
+            Err(e) => {
+                error!("Failed to read the height of chain {}: {}", chain_id, e);
+                None
+            }
+        };
+        let checkpoint = self.checkpoints.as_ref().and_then(|c| c.get(chain_id));
+        if let (false, Some(height), Some(checkpoint)) = (force, height, checkpoint) {
+            if height <= checkpoint {
+                info!(
+                    "Chain {} hasn't advanced past block {}, skipping sync",
+                    chain_id, checkpoint
+                );
+                health::record_success();
+                return;
+            }
+        }
+
+        let ctx = SyncContext {
+            app: &self.app,
+            supabase: self.supabase.as_deref(),
+            buffers: self.buffers.as_ref(),
+            strict_schema: self.strict_schema,
+            print: self.print,
+            deadline: self.cycle_budget.map(|budget| Instant::now() + budget),
+            write_policy: self.write_policy,
+        };
+        let mut prefetched = self.prefetch().await.into_iter();
+        for (i, target) in self.targets.iter().enumerate() {
+            let response = prefetched.next().flatten();
+            if ctx.budget_exhausted() {
+                self.defer(&self.targets[i..]);
+                failed = true;
+                break;
+            }
+            if let Err(e) = self.sync_target(target.as_ref(), response, &ctx).await {
+                if e.is::<BudgetExhausted>() {
+                    self.defer(&self.targets[i..]);
+                    failed = true;
+                    break;
+                }
+                if self.write_policy == WritePolicy::FailFast {
+                    self.halt(e);
+                    return;
+                }
+                if !SupabaseError::is_missing_table(&e) {
+                    error!("{:#}", e);
+                }
+                failed = true;
+            }
+        }
+
+        if !failed {
+            health::record_success();
+            if let (Some(height), Some(checkpoints)) = (height, &self.checkpoints) {
+                if let Err(e) = checkpoints.set(chain_id, height) {
+                    error!("{:#}", e);
+                }
+            }
+        }
+    }
+
+    /// Stops syncing for good and wakes up [`Watcher::halted`].
+    fn halt(&self, error: anyhow::Error) {
+        error!("Stopping on the first failure (--fail-fast): {:#}", error);
+        self.closed.store(true, Ordering::Relaxed);
+        *self.halt_error.lock().unwrap() = Some(error);
+        self.halted.cancel();
+    }
+
+    /// Completes once a failure stopped the watcher under [`WritePolicy::FailFast`].
+    pub async fn halted(&self) {
+        self.halted.cancelled().await
+    }
+
+    /// The failure that stopped the watcher, if any.
+    pub fn take_halt_error(&self) -> Option<anyhow::Error> {
+        self.halt_error.lock().unwrap().take()
+    }
+
+    /// Waits for the running sync, if any, then flushes the write buffers.
+    /// No sync runs afterwards, so nothing is written to a client that is
+    /// shutting down.
+    pub async fn drain(&self) {
+        let _guard = self.cycles.lock().await;
+        self.closed.store(true, Ordering::Relaxed);
+        if let Some(buffers) = &self.buffers {
+            let results = [
+                buffers.tournaments.flush().await,
+                buffers.participants.flush().await,
+                buffers.count.flush().await,
+            ];
+            for e in results.into_iter().filter_map(Result::err) {
+                error!("Failed to flush buffered writes: {:#}", e);
+            }
+        }
+    }
+
+    fn defer(&self, remaining: &[Box<dyn SyncTarget>]) {
+        METRICS
+            .sync_budget_exhausted_total
+            .fetch_add(1, Ordering::Relaxed);
+        let names: Vec<&str> = remaining.iter().map(|t| t.name()).collect();
+        warn!(
+            "Sync budget of {:?} exhausted, deferring {} to the next cycle",
+            self.cycle_budget.unwrap_or_default(),
+            names.join(", ")
+        );
+    }
+
+    /// Fetches the queries of all targets in one batched request. Yields
+    /// `None` for every target when they can't be batched or the batch fails,
+    /// so each target then sends its own query.
+    async fn prefetch(&self) -> Vec<Option<String>> {
+        let queries: Vec<String> = self.targets.iter().map(|t| t.query()).collect();
+        if queries.len() < 2 {
+            return vec![None; queries.len()];
+        }
+        let queries: Vec<&str> = queries.iter().map(String::as_str).collect();
+        match self.app.query_batch(&queries).await {
+            Ok(responses) => responses.into_iter().map(Some).collect(),
+            Err(e) => {
+                debug!("Querying targets one by one: {:#}", e);
+                vec![None; queries.len()]
+            }
+        }
+    }
+
+    async fn sync_target(
+        &self,
+        target: &dyn SyncTarget,
+        prefetched: Option<String>,
+        ctx: &SyncContext<'_>,
+    ) -> Result<()> {
+        let response = match prefetched {
+            Some(response) => response,
+            None => self
+                .app
+                .query(&target.query())
+                .await
+                .with_context(|| format!("{} query failed", target.name()))?,
+        };
+        target.apply(&response, ctx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Answers no query; for targets fed their response directly
+    struct Offline;
+
+    #[async_trait]
+    impl QueryExecutor for Offline {
+        async fn query(&self, query: &str) -> Result<String> {
+            anyhow::bail!("unexpected query {}", query)
+        }
+    }
+
+    fn offline_context(app: &Offline) -> SyncContext<'_> {
+        SyncContext {
+            app,
+            supabase: None,
+            buffers: None,
+            strict_schema: false,
+            print: false,
+            deadline: None,
+            write_policy: WritePolicy::KeepGoing,
+        }
+    }
+
+    /// Counts how many `apply` calls run at once, each taking a while
+    #[derive(Default)]
+    struct SlowTarget {
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+        applied: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl SyncTarget for SlowTarget {
+        fn name(&self) -> &'static str {
+            "Slow"
+        }
+
+        fn query(&self) -> String {
+            String::new()
+        }
+
+        async fn apply(&self, _response: &str, _ctx: &SyncContext<'_>) -> Result<()> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.applied.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn back_to_back_syncs_never_overlap_and_coalesce() {
+        let gate = CycleGate::default();
+        let target = SlowTarget::default();
+        let app = Offline;
+        let ctx = offline_context(&app);
+
+        // All requests arrive while the first cycle is still running.
+        let requests = (0..10u64).map(|i| {
+            let (gate, target, ctx) = (&gate, &target, &ctx);
+            async move {
+                tokio::time::sleep(Duration::from_millis(2 * i)).await;
+                gate.run(false, move |_| async move {
+                    target.apply("", ctx).await.unwrap();
+                })
+                .await
+            }
+        });
+        futures::future::join_all(requests).await;
+
+        assert_eq!(target.max_running.load(Ordering::SeqCst), 1);
+        let cycles = target.applied.load(Ordering::SeqCst);
+        assert!((1..=2).contains(&cycles), "{} cycles ran", cycles);
+    }
+
+    #[tokio::test]
+    async fn request_after_a_cycle_runs_again() {
+        let gate = CycleGate::default();
+        let target = SlowTarget::default();
+        let app = Offline;
+        let ctx = offline_context(&app);
+        for _ in 0..2 {
+            gate.run(false, |_| async {
+                target.apply("", &ctx).await.unwrap();
+            })
+            .await;
+        }
+        assert_eq!(target.applied.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn forced_request_is_passed_to_the_rerun() {
+        let gate = CycleGate::default();
+        let forced = std::sync::Mutex::new(Vec::new());
+        let first = gate.run(false, |force| {
+            forced.lock().unwrap().push(force);
+            tokio::time::sleep(Duration::from_millis(50))
+        });
+        let second = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            gate.run(true, |force| {
+                forced.lock().unwrap().push(force);
+                tokio::time::sleep(Duration::ZERO)
+            })
+            .await
+        };
+        tokio::join!(first, second);
+        assert_eq!(*forced.lock().unwrap(), [false, true]);
+    }
+}
@@ -0,0 +1,1048 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use super::{BudgetExhausted, SyncContext, SyncTarget, Table, WatchConfig};
+use crate::graphql;
+use crate::logging::{emoji, mark_ok, short_id, truncate_body};
+use crate::models::participants::{BracketResponse, Participants, ParticipantsDB};
+use crate::models::tournament::{
+    Micros, ParticipantResponse, Tournament, TournamentDB, TournamentParticipant,
+    TournamentParticipantDB, TournamentResponse,
+};
+use crate::models::{
+    CountResponse, GameCount, LeaderBoardResponse, Leaderboard, LeaderboardDB, MatchHistoryDB,
+    MatchHistoryResponse,
+};
+use crate::supabase::{Filter, SupabaseError, SupabaseModel};
+
+/// Consecutive empty `allTournaments` results needed before pruning, so a
+/// single odd response can't wipe the table
+const PRUNE_CONFIRMATIONS: u32 = 2;
+
+/// Builds the targets syncing `tables`, in the order they run.
+pub fn registry(config: &WatchConfig) -> Vec<Box<dyn SyncTarget>> {
+    let tables = &config.tables;
+    let mut targets: Vec<Box<dyn SyncTarget>> = Vec::new();
+
+    // Participants and brackets are queried per tournament, so they need the tournaments list too.
+    let tournaments = TournamentsTarget {
+        tournaments: tables.contains(&Table::Tournaments),
+        participants: tables.contains(&Table::Participants),
+        brackets: tables.contains(&Table::Brackets),
+        participants_page_size: config.participants_page_size,
+        since: config.tournaments_since,
+        since_server_side: config.tournaments_since_server_side,
+        prune: config.prune_tournaments,
+        concurrency: config.tournament_concurrency,
+        skip_inconsistent_prizes: config.skip_inconsistent_prizes,
+        cache: Mutex::default(),
+    };
+    if tournaments.tournaments || tournaments.participants || tournaments.brackets {
+        targets.push(Box::new(tournaments));
+    }
+    if tables.contains(&Table::Leaderboard) {
+        targets.push(Box::<LeaderboardTarget>::default());
+    }
+    if tables.contains(&Table::Count) {
+        targets.push(Box::<CountTarget>::default());
+    }
+    if tables.contains(&Table::Matches) {
+        targets.push(Box::new(MatchesTarget {
+            seen: Mutex::new(RecentKeys::new(config.match_dedup_window)),
+        }));
+    }
+    targets
+}
+
+#[derive(Default)]
+struct TournamentsCache {
+    tournaments: HashMap<String, Tournament>,
+    participants: HashMap<String, HashMap<String, TournamentParticipant>>,
+    brackets: HashMap<String, Participants>,
+    /// Consecutive syncs that found no tournaments while some were cached
+    empty_streak: u32,
+}
+
+impl TournamentsCache {
+    /// Moves the entries of one tournament into a cache of their own, so
+    /// tournaments can be synced concurrently.
+    fn take(&mut self, tournament_id: &str) -> Self {
+        let mut own = Self::default();
+        if let Some(tournament) = self.tournaments.remove(tournament_id) {
+            own.tournaments
+                .insert(tournament_id.to_string(), tournament);
+        }
+        if let Some(participants) = self.participants.remove(tournament_id) {
+            own.participants
+                .insert(tournament_id.to_string(), participants);
+        }
+        if let Some(bracket) = self.brackets.remove(tournament_id) {
+            own.brackets.insert(tournament_id.to_string(), bracket);
+        }
+        own
+    }
+
+    /// Puts back entries moved out by [`TournamentsCache::take`].
+    fn merge(&mut self, own: Self) {
+        self.tournaments.extend(own.tournaments);
+        self.participants.extend(own.participants);
+        self.brackets.extend(own.brackets);
+    }
+}
+
+/// Tournaments, plus the participants and bracket of each tournament
+struct TournamentsTarget {
+    tournaments: bool,
+    participants: bool,
+    brackets: bool,
+    /// Fetch participants in pages of this many, instead of all at once
+    participants_page_size: Option<usize>,
+    /// Skip tournaments starting before this time
+    since: Option<Micros>,
+    /// Let the service filter by `since`, see [`graphql::tournaments`]
+    since_server_side: bool,
+    /// Delete the synced tournaments once the service reports none
+    prune: bool,
+    /// Tournaments synced at the same time
+    concurrency: usize,
+    /// Leave out tournaments whose prize data is inconsistent
+    skip_inconsistent_prizes: bool,
+    cache: Mutex<TournamentsCache>,
+}
+
+#[async_trait]
+impl SyncTarget for TournamentsTarget {
+    fn name(&self) -> &'static str {
+        "Tournaments"
+    }
+
+    fn query(&self) -> String {
+        let since = self.since.filter(|_| self.since_server_side);
+        graphql::tournaments(since).to_json()
+    }
+
+    async fn apply(&self, response: &str, ctx: &SyncContext<'_>) -> Result<()> {
+        let tournaments_resp: TournamentResponse = match ctx.parse(response, "tournaments") {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                debug!("Tournaments query returned an unexpected shape: {:#}", e);
+                return Err(e);
+            }
+        };
+        let mut cache = self.cache.lock().await;
+
+        if tournaments_resp.data.all_tournaments.is_empty() {
+            debug!("Tournaments query returned no tournaments");
+            if self.tournaments && self.prune && !cache.tournaments.is_empty() {
+                return prune_tournaments(response, &mut cache, ctx).await;
+            }
+        }
+        cache.empty_streak = 0;
+
+        // Also applied after server-side filtering, in case the service ignores `since`.
+        let relevant = tournaments_resp
+            .data
+            .all_tournaments
+            .into_iter()
+            .filter(|t| self.since.is_none_or(|since| t.starting_time >= since));
+        // Each tournament works on its own slice of the cache, merged back
+        // once all are done, whether they succeeded or not.
+        let jobs: Vec<_> = relevant
+            .map(|tournament| {
+                let own = cache.take(&tournament.tournament_id);
+                (tournament, own)
+            })
+            .collect();
+        let results: Vec<_> = stream::iter(jobs)
+            .map(|(tournament, mut own)| async move {
+                let result = self.sync_one(&tournament, &mut own, ctx).await;
+                (own, result)
+            })
+            .buffer_unordered(self.concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut first_error = None;
+        for (own, result) in results {
+            cache.merge(own);
+            if let Err(e) = result {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+impl TournamentsTarget {
+    /// Syncs one tournament and whichever of its participants and bracket
+    /// are enabled, against that tournament's slice of the cache.
+    async fn sync_one(
+        &self,
+        tournament: &Tournament,
+        cache: &mut TournamentsCache,
+        ctx: &SyncContext<'_>,
+    ) -> Result<()> {
+        // Each tournament is written independently, so stopping here
+        // leaves the cache consistent with what reached Supabase.
+        if ctx.budget_exhausted() {
+            return Err(BudgetExhausted.into());
+        }
+        if self.skip_inconsistent_prizes {
+            if let Err(e) = tournament.check_prize() {
+                tracing::warn!("Skipping tournament {}: {}", tournament.tournament_id, e);
+                return Ok(());
+            }
+        }
+        if self.tournaments {
+            sync_tournament(tournament, cache, ctx).await?;
+        }
+        if self.participants {
+            sync_participants(
+                &tournament.tournament_id,
+                self.participants_page_size,
+                cache,
+                ctx,
+            )
+            .await?;
+        }
+        if self.brackets {
+            if let Err(e) = sync_bracket(&tournament.tournament_id, cache, ctx).await {
+                ctx.write_failed(e)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Deletes every tournament after [`PRUNE_CONFIRMATIONS`] consecutive empty
+/// results, each free of GraphQL errors. A failed query never gets here, and
+/// a partial response carrying `errors` resets the count.
+async fn prune_tournaments(
+    response: &str,
+    cache: &mut TournamentsCache,
+    ctx: &SyncContext<'_>,
+) -> Result<()> {
+    let authoritative = serde_json::from_str::<serde_json::Value>(response)
+        .is_ok_and(|body| body.get("errors").is_none());
+    if !authoritative {
+        cache.empty_streak = 0;
+        tracing::warn!("Empty tournaments list came with errors, not pruning");
+        return Ok(());
+    }
+
+    cache.empty_streak += 1;
+    if cache.empty_streak < PRUNE_CONFIRMATIONS {
+        info!(
+            "No tournaments left ({} cached), pruning once confirmed by the next sync",
+            cache.tournaments.len()
+        );
+        return Ok(());
+    }
+
+    ctx.print(
+        TournamentDB::table_name(),
+        "delete_all",
+        &serde_json::json!({}),
+    );
+    if let Some(supabase) = ctx.supabase {
+        supabase
+            .delete_all::<TournamentDB>()
+            .await
+            .context("Failed to prune tournaments")?;
+    }
+    info!(
+        "{} Pruned {} tournaments",
+        mark_ok(),
+        cache.tournaments.len()
+    );
+    *cache = TournamentsCache::default();
+    Ok(())
+}
+
+/// Whether `current` has to be written, given the version last written.
+/// Timestamp-only updates don't count, see [`Tournament::semantically_equal`].
+fn tournament_changed(cached: Option<&Tournament>, current: &Tournament) -> bool {
+    cached.is_none_or(|cached| !cached.semantically_equal(current))
+}
+
+/// How a tournament's participants differ from those last written
+struct ParticipantsDiff<'a> {
+    /// New participants and those whose data changed
+    changed: Vec<&'a TournamentParticipant>,
+    /// Ids written before that are no longer listed
+    departed: Vec<String>,
+}
+
+fn diff_participants<'a>(
+    cached: &HashMap<String, TournamentParticipant>,
+    current: &'a HashMap<String, TournamentParticipant>,
+) -> ParticipantsDiff<'a> {
+    let changed = current
+        .iter()
+        .filter(|(id, participant)| cached.get(*id) != Some(*participant))
+        .map(|(_, participant)| participant)
+        .collect();
+    let departed = cached
+        .keys()
+        .filter(|id| !current.contains_key(*id))
+        .cloned()
+        .collect();
+    ParticipantsDiff { changed, departed }
+}
+
+async fn sync_tournament(
+    tournament: &Tournament,
+    cache: &mut TournamentsCache,
+    ctx: &SyncContext<'_>,
+) -> Result<()> {
+    if tournament_changed(cache.tournaments.get(&tournament.tournament_id), tournament) {
+        info!(
+            "Tournament {} changed or new, updating Supabase...",
+            tournament.tournament_id
+        );
+        match write_tournament(tournament.for_db(), ctx).await {
+            Ok(_) => {
+                info!(
+                    "{} Updated tournament {} in Supabase",
+                    mark_ok(),
+                    tournament.tournament_name
+                );
+                cache
+                    .tournaments
+                    .insert(tournament.tournament_id.clone(), tournament.clone());
+            }
+            // Already warned about once by the client
+            Err(e) if SupabaseError::is_missing_table(&e) => {}
+            Err(e) => ctx.write_failed(e.context("Failed to update tournament"))?,
+        }
+    }
+    Ok(())
+}
+
+async fn sync_participants(
+    tournament_id: &str,
+    page_size: Option<usize>,
+    cache: &mut TournamentsCache,
+    ctx: &SyncContext<'_>,
+) -> Result<()> {
+    let participants = fetch_participants(tournament_id, page_size, ctx).await?;
+    let current_participants_map = index_participants(participants).with_context(|| {
+        format!(
+            "Skipping participants of tournament {}: corrupt response",
+            tournament_id
+        )
+    })?;
+
+    let first_sync = !cache.participants.contains_key(tournament_id);
+    let tournament_participants_cache = cache
+        .participants
+        .entry(tournament_id.to_string())
+        .or_default();
+
+    let ParticipantsDiff { changed, departed } =
+        diff_participants(tournament_participants_cache, &current_participants_map);
+    let changed: Vec<_> = changed
+        .into_iter()
+        .filter(|participant| match participant.player.validate() {
+            Ok(()) => {
+                debug!("Participant {} changed or new", short_id(&participant.id));
+                true
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Skipping participant {} with invalid stats: {}",
+                    short_id(&participant.id),
+                    e
+                );
+                false
+            }
+        })
+        .collect();
+
+    // Changed participants of a tournament are written with one request.
+    if !changed.is_empty() {
+        let records = changed
+            .iter()
+            .map(|p| p.for_db(tournament_id.to_string()))
+            .collect();
+        match write_participants(records, ctx).await {
+            Ok(_) => {
+                info!(
+                    "{} Updated {} participants of tournament {} in Supabase",
+                    mark_ok(),
+                    changed.len(),
+                    tournament_id
+                );
+                for participant in changed {
+                    tournament_participants_cache
+                        .insert(participant.id.clone(), participant.clone());
+                }
+            }
+            Err(e) if SupabaseError::is_missing_table(&e) => {}
+            Err(e) => ctx.write_failed(e.context("Failed to update participants"))?,
+        }
+    }
+
+    // Remove players who left the tournament. The first sync of a tournament always
+    // prunes, since rows may have been left behind while the watcher wasn't running.
+    if first_sync || !departed.is_empty() {
+        let filters = prune_filters(tournament_id, &current_participants_map);
+        if !departed.is_empty() {
+            ctx.print(
+                TournamentParticipantDB::table_name(),
+                "delete",
+                &serde_json::json!({ "tournament_id": tournament_id, "ids": departed }),
+            );
+        }
+        // Departed participants stay cached on failure, so the next sync retries.
+        match prune_participants(&filters, ctx).await {
+            Ok(()) => {
+                for id in departed {
+                    info!(
+                        "{} Removed participant {} from tournament {}",
+                        mark_ok(),
+                        short_id(&id),
+                        tournament_id
+                    );
+                    tournament_participants_cache.remove(&id);
+                }
+            }
+            Err(e) if SupabaseError::is_missing_table(&e) => {}
+            Err(e) => ctx.write_failed(e.context("Failed to prune departed participants"))?,
+        }
+    }
+    Ok(())
+}
+
+/// Matches the participant rows of a tournament that aren't in `current`.
+fn prune_filters(
+    tournament_id: &str,
+    current: &HashMap<String, TournamentParticipant>,
+) -> Vec<(&'static str, Filter)> {
+    let mut filters = vec![("tournament_id", Filter::Eq(tournament_id.to_string()))];
+    if !current.is_empty() {
+        let mut ids: Vec<String> = current.keys().cloned().collect();
+        ids.sort();
+        filters.push(("id", Filter::NotIn(ids)));
+    }
+    filters
+}
+
+/// Fetches every participant of a tournament, page by page when `page_size`
+/// is set, until a page comes back short.
+async fn fetch_participants(
+    tournament_id: &str,
+    page_size: Option<usize>,
+    ctx: &SyncContext<'_>,
+) -> Result<Vec<TournamentParticipant>> {
+    let Some(limit) = page_size else {
+        let response = ctx
+            .app
+            .query(&graphql::participants(tournament_id).to_json())
+            .await
+            .context("Participants query failed")?;
+        let participants_resp: ParticipantResponse = ctx.parse(&response, "participants")?;
+        return Ok(participants_resp.data.participants);
+    };
+
+    let mut participants = Vec::new();
+    loop {
+        let offset = participants.len();
+        let response = ctx
+            .app
+            .query(&graphql::participants_page(tournament_id, limit, offset).to_json())
+            .await
+            .with_context(|| format!("Participants query failed at offset {}", offset))?;
+        let page: ParticipantResponse = ctx.parse(&response, "participants")?;
+        let fetched = page.data.participants.len();
+        // A service ignoring `offset` would otherwise be paged forever.
+        if offset > 0 && page.data.participants.first() == participants.first() {
+            anyhow::bail!(
+                "participants of tournament {} don't paginate, run without --participants-page-size",
+                tournament_id
+            );
+        }
+        participants.extend(page.data.participants);
+        if fetched < limit {
+            return Ok(participants);
+        }
+    }
+}
+
+/// Indexes participants by id. A repeated id with identical data is logged
+/// and collapsed; conflicting entries for one id make the whole response
+/// untrustworthy, since pruning from it could delete the wrong rows.
+fn index_participants(
+    participants: Vec<TournamentParticipant>,
+) -> Result<HashMap<String, TournamentParticipant>> {
+    let mut map = HashMap::with_capacity(participants.len());
+    for participant in participants {
+        match map.get(&participant.id) {
+            Some(existing) if *existing == participant => {
+                tracing::warn!("Participant {} listed twice", short_id(&participant.id));
+            }
+            Some(_) => {
+                anyhow::bail!(
+                    "participant {} listed twice with different data",
+                    short_id(&participant.id)
+                );
+            }
+            None => {
+                map.insert(participant.id.clone(), participant);
+            }
+        }
+    }
+    Ok(map)
+}
+
+async fn sync_bracket(
+    tournament_id: &str,
+    cache: &mut TournamentsCache,
+    ctx: &SyncContext<'_>,
+) -> Result<()> {
+    let response = ctx
+        .app
+        .query(&graphql::bracket(tournament_id).to_json())
+        .await
+        .context("Bracket query failed")?;
+    let bracket_resp: BracketResponse = ctx.parse(&response, "bracket")?;
+    let Some(encoded) = bracket_resp.data.encoded_participants else {
+        return Ok(());
+    };
+    let bracket = Participants::decode(&encoded)
+        .with_context(|| format!("Failed to decode bracket of tournament {}", tournament_id))?;
+
+    if cache.brackets.get(tournament_id) != Some(&bracket) {
+        info!(
+            "Bracket of tournament {} changed, updating Supabase...",
+            tournament_id
+        );
+        let record = bracket.for_db(tournament_id.to_string())?;
+        ctx.print(ParticipantsDB::table_name(), "upsert", &record);
+        if let Some(supabase) = ctx.supabase {
+            record
+                .insert(supabase)
+                .await
+                .context("Failed to update bracket")?;
+        }
+        info!(
+            "{} Updated bracket of tournament {} in Supabase",
+            mark_ok(),
+            tournament_id
+        );
+        cache.brackets.insert(tournament_id.to_string(), bracket);
+    }
+    Ok(())
+}
+
+async fn write_tournament(record: TournamentDB, ctx: &SyncContext<'_>) -> Result<()> {
+    ctx.print(TournamentDB::table_name(), "upsert", &record);
+    match (ctx.buffers, ctx.supabase) {
+        (Some(buffers), _) => buffers.tournaments.push(record).await,
+        // Use insert which maps to upsert for TournamentDB to avoid full delete/insert cycle
+        (None, Some(supabase)) => record.insert(supabase).await,
+        (None, None) => Ok(()),
+    }
+}
+
+async fn prune_participants(filters: &[(&str, Filter)], ctx: &SyncContext<'_>) -> Result<()> {
+    match ctx.supabase {
+        Some(supabase) => supabase
+            .delete_where::<TournamentParticipantDB>(filters)
+            .await
+            .map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+async fn write_participants(
+    records: Vec<TournamentParticipantDB>,
+    ctx: &SyncContext<'_>,
+) -> Result<()> {
+    for record in &records {
+        ctx.print(TournamentParticipantDB::table_name(), "upsert", record);
+    }
+    match (ctx.buffers, ctx.supabase) {
+        (Some(buffers), _) => {
+            for record in records {
+                buffers.participants.push(record).await?;
+            }
+            Ok(())
+        }
+        (None, Some(supabase)) => supabase.upsert_many(&records).await,
+        (None, None) => Ok(()),
+    }
+}
+
+#[derive(Default)]
+struct LeaderboardTarget {
+    cache: Mutex<Option<Vec<Leaderboard>>>,
+}
+
+#[async_trait]
+impl SyncTarget for LeaderboardTarget {
+    fn name(&self) -> &'static str {
+        "Leaderboard"
+    }
+
+    fn query(&self) -> String {
+        graphql::leaderboard().to_json()
+    }
+
+    async fn apply(&self, response: &str, ctx: &SyncContext<'_>) -> Result<()> {
+        let leaderboard_data: LeaderBoardResponse = ctx.parse(response, "leaderboard")?;
+        let new_leaderboard = leaderboard_data.data.leaderboard;
+        let mut cache = self.cache.lock().await;
+
+        // Update leaderboard if changed
+        if cache.as_ref() != Some(&new_leaderboard) {
+            info!(
+                "Leaderboard changed, updating {} entries",
+                new_leaderboard.len()
+            );
+
+            // Ranks depend on every entry, so they are recomputed with each replace.
+            let rows = Leaderboard::ranked_for_db(&new_leaderboard);
+            ctx.print(LeaderboardDB::table_name(), "replace_all", &rows);
+            if let Some(supabase) = ctx.supabase {
+                LeaderboardDB::replace_all(rows, supabase)
+                    .await
+                    .context("Failed to update leaderboard")?;
+            }
+            info!("{} Updated leaderboard in Supabase", mark_ok());
+            *cache = Some(new_leaderboard);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct CountTarget {
+    cache: Mutex<Option<u64>>,
+}
+
+#[async_trait]
+impl SyncTarget for CountTarget {
+    fn name(&self) -> &'static str {
+        "Count"
+    }
+
+    fn query(&self) -> String {
+        graphql::count().to_json()
+    }
+
+    async fn apply(&self, response: &str, ctx: &SyncContext<'_>) -> Result<()> {
+        let count_data: CountResponse = ctx.parse(response, "count")?;
+        let new_count = count_data.data.count;
+        let mut cache = self.cache.lock().await;
+
+        // Update count if changed
+        if *cache != Some(new_count) {
+            info!(
+                "{}Count changed: {:?} -> {}",
+                emoji("📊 "),
+                *cache,
+                new_count
+            );
+
+            let count_record = GameCount {
+                id: "singleton".to_string(),
+                count: new_count,
+            };
+
+            ctx.print(GameCount::table_name(), "upsert", &count_record);
+            match (ctx.buffers, ctx.supabase) {
+                (Some(buffers), _) => buffers.count.push(count_record).await,
+                (None, Some(supabase)) => count_record.insert(supabase).await,
+                (None, None) => Ok(()),
+            }
+            .context("Failed to update count")?;
+            info!("{} Updated count in Supabase", mark_ok());
+            *cache = Some(new_count);
+        }
+        Ok(())
+    }
+}
+
+/// The most recently seen distinct keys, evicting the least recently seen
+/// once `capacity` is reached
+struct RecentKeys {
+    capacity: usize,
+    keys: VecDeque<String>,
+}
+
+impl RecentKeys {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            keys: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Whether `key` was seen recently, making it the most recent if so.
+    fn contains(&mut self, key: &str) -> bool {
+        match self.keys.iter().position(|k| k == key) {
+            Some(i) => {
+                let key = self.keys.remove(i).expect("index from position");
+                self.keys.push_back(key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, key: String) {
+        if self.contains(&key) {
+            return;
+        }
+        if self.keys.len() == self.capacity {
+            self.keys.pop_front();
+        }
+        self.keys.push_back(key);
+    }
+}
+
+/// The last match, inserted unless its `blobHash` was seen recently, so an
+/// unchanged `matchHistoryLast` costs no Supabase round-trip
+struct MatchesTarget {
+    seen: Mutex<RecentKeys>,
+}
+
+#[async_trait]
+impl SyncTarget for MatchesTarget {
+    fn name(&self) -> &'static str {
+        "Matches"
+    }
+
+    fn query(&self) -> String {
+        graphql::match_history_last().to_json()
+    }
+
+    async fn apply(&self, response: &str, ctx: &SyncContext<'_>) -> Result<()> {
+        let match_history: MatchHistoryResponse = ctx.parse(response, "match history")?;
+        let mut seen = self.seen.lock().await;
+
+        if let Some(new_match) = match_history.data.match_history_last {
+            if !seen.contains(&new_match.blob_hash) {
+                debug!(
+                    "Last match update: {}",
+                    truncate_body(&format!("{:?}", new_match))
+                );
+
+                let record = new_match.for_db();
+                ctx.print(MatchHistoryDB::table_name(), "insert", &record);
+                if let Some(supabase) = ctx.supabase {
+                    MatchHistoryDB::insert(&record, supabase)
+                        .await
+                        .context("Failed to update matches list")?;
+                }
+                info!("{} Updated matches list in Supabase", mark_ok());
+                seen.insert(new_match.blob_hash);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::source::{Recorder, Replayer};
+    use crate::chain::QueryExecutor;
+    use crate::models::tournament::PlayerInfo;
+    use crate::watcher::WritePolicy;
+
+    /// Answers queries from a fixed set of responses and remembers what was
+    /// asked, standing in for the application
+    #[derive(Default)]
+    struct FakeApp {
+        responses: HashMap<String, String>,
+        asked: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl FakeApp {
+        fn answer(mut self, query: String, response: serde_json::Value) -> Self {
+            self.responses.insert(query, response.to_string());
+            self
+        }
+
+        fn asked(&self) -> Vec<String> {
+            self.asked.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl QueryExecutor for FakeApp {
+        async fn query(&self, query: &str) -> Result<String> {
+            self.asked.lock().unwrap().push(query.to_string());
+            self.responses
+                .get(query)
+                .cloned()
+                .with_context(|| format!("no response for {}", query))
+        }
+    }
+
+    /// A context that only reads from `app`, writing nothing
+    fn context(app: &dyn QueryExecutor) -> SyncContext<'_> {
+        SyncContext {
+            app,
+            supabase: None,
+            buffers: None,
+            strict_schema: false,
+            print: false,
+            deadline: None,
+            write_policy: WritePolicy::KeepGoing,
+        }
+    }
+
+    /// Syncs only the participants of each tournament
+    fn participants_target(page_size: Option<usize>) -> TournamentsTarget {
+        TournamentsTarget {
+            tournaments: false,
+            participants: true,
+            brackets: false,
+            participants_page_size: page_size,
+            since: None,
+            since_server_side: false,
+            prune: false,
+            concurrency: 1,
+            skip_inconsistent_prizes: false,
+            cache: Mutex::default(),
+        }
+    }
+
+    fn tournaments_response(tournaments: &[Tournament]) -> String {
+        serde_json::json!({ "data": { "allTournaments": tournaments } }).to_string()
+    }
+
+    fn participants_response(participants: &[TournamentParticipant]) -> serde_json::Value {
+        serde_json::json!({ "data": { "participants": participants } })
+    }
+
+    fn participant(id: &str, elo: u32) -> TournamentParticipant {
+        TournamentParticipant {
+            id: id.to_string(),
+            player: PlayerInfo {
+                name: Some(id.to_string()),
+                elo,
+                matches: 1,
+                ath: elo,
+            },
+        }
+    }
+
+    fn by_id(participants: &[TournamentParticipant]) -> HashMap<String, TournamentParticipant> {
+        participants
+            .iter()
+            .map(|participant| (participant.id.clone(), participant.clone()))
+            .collect()
+    }
+
+    fn ids(participants: &[&TournamentParticipant]) -> Vec<String> {
+        let mut ids: Vec<String> = participants.iter().map(|p| p.id.clone()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn new_tournament_is_written() {
+        assert!(tournament_changed(None, &Tournament::sample("t1")));
+    }
+
+    #[test]
+    fn unchanged_tournament_is_not_written() {
+        let cached = Tournament::sample("t1");
+        assert!(!tournament_changed(Some(&cached), &cached.clone()));
+    }
+
+    #[test]
+    fn changed_field_is_written() {
+        let cached = Tournament::sample("t1");
+        let current = Tournament {
+            status: "finished".to_string(),
+            ..cached.clone()
+        };
+        assert!(tournament_changed(Some(&cached), &current));
+    }
+
+    #[test]
+    fn timestamp_only_change_is_not_written() {
+        let cached = Tournament::sample("t1");
+        let current = Tournament {
+            updated_at: cached.updated_at + 1,
+            version: "2".to_string(),
+            ..cached.clone()
+        };
+        assert!(!tournament_changed(Some(&cached), &current));
+    }
+
+    #[test]
+    fn unchanged_participants_are_not_written() {
+        let cached = by_id(&[participant("a", 1000), participant("b", 1200)]);
+        let current = cached.clone();
+        let diff = diff_participants(&cached, &current);
+        assert!(diff.changed.is_empty());
+        assert!(diff.departed.is_empty());
+    }
+
+    #[test]
+    fn new_and_changed_participants_are_written() {
+        let cached = by_id(&[participant("a", 1000), participant("b", 1200)]);
+        let current = by_id(&[
+            participant("a", 1010),
+            participant("b", 1200),
+            participant("c", 900),
+        ]);
+        let diff = diff_participants(&cached, &current);
+        assert_eq!(ids(&diff.changed), ["a", "c"]);
+        assert!(diff.departed.is_empty());
+    }
+
+    #[test]
+    fn removed_participant_is_departed() {
+        let cached = by_id(&[participant("a", 1000), participant("b", 1200)]);
+        let current = by_id(&[participant("a", 1000)]);
+        let diff = diff_participants(&cached, &current);
+        assert!(diff.changed.is_empty());
+        assert_eq!(diff.departed, ["b"]);
+    }
+
+    #[tokio::test]
+    async fn apply_fetches_participants_through_the_executor() {
+        let participants = [participant("p1", 1200), participant("p2", 1300)];
+        let app = FakeApp::default().answer(
+            graphql::participants("t1").to_json(),
+            participants_response(&participants),
+        );
+        let target = participants_target(None);
+
+        let response = tournaments_response(&[Tournament::sample("t1")]);
+        target.apply(&response, &context(&app)).await.unwrap();
+
+        assert_eq!(app.asked(), [graphql::participants("t1").to_json()]);
+        let cache = target.cache.lock().await;
+        assert_eq!(cache.participants["t1"], by_id(&participants));
+    }
+
+    #[tokio::test]
+    async fn failed_participants_query_fails_the_sync() {
+        let app = FakeApp::default();
+        let target = participants_target(None);
+
+        let response = tournaments_response(&[Tournament::sample("t1")]);
+        let error = target.apply(&response, &context(&app)).await.unwrap_err();
+
+        assert!(format!("{:#}", error).contains("Participants query failed"));
+        assert!(target.cache.lock().await.participants.is_empty());
+    }
+
+    /// A fresh directory for the captures of one test
+    fn capture_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("pws-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Records the participants of tournament t1 as `pages` of `limit`.
+    fn record_pages(dir: &std::path::Path, limit: usize, pages: &[&[TournamentParticipant]]) {
+        let recorder = Recorder::new(dir).unwrap();
+        let mut offset = 0;
+        for page in pages {
+            let query = graphql::participants_page("t1", limit, offset).to_json();
+            let response = participants_response(page).to_string();
+            recorder.record(&query, &response).unwrap();
+            offset += page.len();
+        }
+    }
+
+    #[tokio::test]
+    async fn participants_are_fetched_until_a_short_page() {
+        let all: Vec<_> = (1..=5)
+            .map(|i| participant(&format!("p{}", i), 1200))
+            .collect();
+        let dir = capture_dir("paged-participants");
+        record_pages(&dir, 2, &[&all[..2], &all[2..4], &all[4..]]);
+        let replayer = Replayer::load(&dir).unwrap();
+
+        let fetched = fetch_participants("t1", Some(2), &context(&replayer)).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(fetched.unwrap(), all);
+    }
+
+    #[tokio::test]
+    async fn service_ignoring_offset_is_not_paged_forever() {
+        let page = [participant("p1", 1200), participant("p2", 1200)];
+        let dir = capture_dir("unpaged-participants");
+        record_pages(&dir, 2, &[&page, &page]);
+        let replayer = Replayer::load(&dir).unwrap();
+
+        let fetched = fetch_participants("t1", Some(2), &context(&replayer)).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let error = fetched.unwrap_err();
+        assert!(error.to_string().contains("don't paginate"), "{:#}", error);
+    }
+
+    /// `filters` as the PostgREST query they send
+    fn rendered(filters: &[(&str, Filter)]) -> Vec<String> {
+        filters
+            .iter()
+            .map(|(column, filter)| format!("{}={}", column, filter.to_query_value()))
+            .collect()
+    }
+
+    #[test]
+    fn prune_keeps_only_current_participants() {
+        let current = by_id(&[participant("p2", 1200), participant("p1", 1200)]);
+        assert_eq!(
+            rendered(&prune_filters("t1", &current)),
+            ["tournament_id=eq.t1", r#"id=not.in.("p1","p2")"#]
+        );
+    }
+
+    #[test]
+    fn prune_without_participants_clears_the_tournament() {
+        assert_eq!(
+            rendered(&prune_filters("t1", &HashMap::new())),
+            ["tournament_id=eq.t1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn departed_participants_leave_the_cache() {
+        let before = [participant("p1", 1200), participant("p2", 1300)];
+        let after = [participant("p2", 1300)];
+        let query = graphql::participants("t1").to_json();
+        let response = tournaments_response(&[Tournament::sample("t1")]);
+        let target = participants_target(None);
+
+        let app = FakeApp::default().answer(query.clone(), participants_response(&before));
+        target.apply(&response, &context(&app)).await.unwrap();
+        let app = FakeApp::default().answer(query, participants_response(&after));
+        target.apply(&response, &context(&app)).await.unwrap();
+
+        let cache = target.cache.lock().await;
+        assert_eq!(cache.participants["t1"], by_id(&after));
+    }
+
+    #[test]
+    fn identical_duplicates_are_collapsed() {
+        let p1 = participant("p1", 1200);
+        let indexed = index_participants(vec![p1.clone(), participant("p2", 1300), p1]).unwrap();
+        assert_eq!(indexed.len(), 2);
+        assert_eq!(indexed["p1"], participant("p1", 1200));
+    }
+
+    #[test]
+    fn conflicting_duplicates_are_rejected() {
+        let participants = vec![participant("p1", 1200), participant("p1", 1250)];
+        let error = index_participants(participants).unwrap_err();
+        assert!(error.to_string().contains("different data"), "{}", error);
+    }
+}